@@ -0,0 +1,112 @@
+use crate::Document;
+use crate::Position;
+
+use std::collections::HashMap;
+
+/// Words from other buffers rank after every word in the current one.
+const OTHER_BUFFER_DISTANCE: usize = usize::MAX;
+
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Collects the words in `current` and `others` that extend `prefix`, ranked
+/// by how close to the cursor `at` in `current` they occur, nearest first,
+/// leaving out the word the cursor is in. Words only found in other buffers
+/// follow, alphabetically.
+pub fn candidates<'a>(
+    prefix: &str,
+    current: &Document,
+    at: &Position,
+    others: impl Iterator<Item = &'a Document>,
+) -> Vec<String> {
+    // The row and byte offset of the cursor, whose word is left out.
+    let cursor = current.row(at.y).map(|row| (at.y, row.byte_index(at.x)));
+    let mut distances: HashMap<String, usize> = HashMap::new();
+    let mut add_words =
+        |document: &Document, distance: &dyn Fn(usize) -> usize, skip: Option<(usize, usize)>| {
+            for y in 0..document.len() {
+                let Some(row) = document.row(y) else {
+                    continue;
+                };
+                for (start, word) in words(row.as_str()) {
+                    let under_cursor = skip.is_some_and(|(skip_y, cursor)| {
+                        skip_y == y && start < cursor && cursor <= start + word.len()
+                    });
+                    if word.len() > prefix.len() && word.starts_with(prefix) && !under_cursor {
+                        let distance = distance(y);
+                        distances
+                            .entry(word.to_string())
+                            .and_modify(|best| *best = (*best).min(distance))
+                            .or_insert(distance);
+                    }
+                }
+            }
+        };
+    add_words(current, &|y| y.abs_diff(at.y), cursor);
+    for document in others {
+        add_words(document, &|_| OTHER_BUFFER_DISTANCE, None);
+    }
+    let mut words: Vec<(String, usize)> = distances.into_iter().collect();
+    words.sort_by(|(a, a_distance), (b, b_distance)| a_distance.cmp(b_distance).then(a.cmp(b)));
+    words.into_iter().map(|(word, _)| word).collect()
+}
+
+/// The runs of word characters in `text`, with their byte offsets.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (start, is_word_char(c)) {
+            (None, true) => start = Some(index),
+            (Some(word_start), false) => {
+                words.push((word_start, &text[word_start..index]));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::default();
+        document.replace(&Position::default(), &Position::default(), text);
+        document
+    }
+
+    fn complete(prefix: &str, text: &str, x: usize, y: usize, others: &[&str]) -> Vec<String> {
+        let others: Vec<Document> = others.iter().map(|text| document(text)).collect();
+        candidates(prefix, &document(text), &Position { x, y }, others.iter())
+    }
+
+    #[test]
+    fn ranks_words_by_distance_from_the_cursor() {
+        let text = "format\nfor\nforge\nfo\nforeach fork";
+        assert_eq!(
+            complete("fo", text, 2, 3, &["fort foreach"]),
+            ["foreach", "forge", "fork", "for", "format", "fort"]
+        );
+    }
+
+    #[test]
+    fn lists_each_word_once() {
+        let text = "value value_of\nvalue val";
+        assert_eq!(
+            complete("val", text, 3, 1, &["value"]),
+            ["value", "value_of"]
+        );
+    }
+
+    #[test]
+    fn leaves_out_the_word_under_the_cursor() {
+        assert!(complete("wor", "word", 3, 0, &[]).is_empty());
+        assert_eq!(complete("wor", "word\nword", 3, 0, &[]), ["word"]);
+        assert_eq!(complete("wor", "word wor", 8, 0, &[]), ["word"]);
+        assert_eq!(complete("é", "éa éb", 2, 0, &[]), ["éb"]);
+    }
+}
@@ -1,25 +1,83 @@
+use crate::editorconfig::{self, Charset, IndentStyle, LineEnding, Properties};
+use crate::grep::Hit;
+use crate::search::Query;
+use crate::undo::{Edit, UndoStack};
 use crate::FileType;
+use crate::Marks;
 use crate::Position;
 use crate::Row;
 use crate::SearchDirection;
 
+use std::cell::OnceCell;
 use std::fs;
-use std::io::{Error, Write};
+use std::io::Error;
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Tabs take a single column unless an `.editorconfig` says otherwise.
+const DEFAULT_TAB_WIDTH: usize = 1;
+/// The width text is reflowed to unless an `.editorconfig` sets
+/// `max_line_length`.
+const DEFAULT_MAX_LINE_LENGTH: usize = 80;
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// The ranges of graphemes in search matches, for each row.
+type RowMatches = Vec<Vec<(usize, usize)>>;
 
 #[derive(Default)]
 pub struct Document {
     rows: Vec<Row>,
     dirty: bool,
+    config: Properties,
     file_type: FileType,
     pub filename: Option<String>,
+    history: UndoStack,
+    /// The search hits listed one per row, in a read-only results buffer.
+    hits: Vec<Hit>,
+    marks: Marks,
+    /// The last query highlighted and the ranges of its matches in each row,
+    /// until the next edit.
+    matches: Option<(Query, RowMatches)>,
+    /// The rows joined for searching, until the next edit.
+    joined: OnceCell<(String, Vec<usize>)>,
+    /// Whether the file started with a UTF-8 byte order mark, which saving
+    /// keeps unless the `.editorconfig` sets a charset.
+    bom: bool,
 }
 
 impl Document {
+    /// Starts treating the edits that follow as one for undo and redo, until
+    /// `end_undo_group`.
+    pub fn begin_undo_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    fn clamp(&self, at: &Position) -> Position {
+        if at.y >= self.rows.len() {
+            return self.end_position();
+        }
+        Position {
+            x: at.x.min(self.rows[at.y].len()),
+            y: at.y,
+        }
+    }
+
     pub fn delete(&mut self, at: &Position) {
         let len = self.len();
-        if at.y == len {
+        if at.y == len || !self.hits.is_empty() {
             return;
         }
+        let next = self.next_position(at);
+        self.marks.adjust(at, &next, at);
+        let removed = self.text(at, &next);
+        if !removed.is_empty() {
+            self.history.push(Edit {
+                at: at.clone(),
+                removed,
+                inserted: String::new(),
+            });
+        }
         if at.x == self.rows.get_mut(at.y).unwrap().len() && at.y < len - 1 {
             let next_row = self.rows.remove(at.y + 1);
             let row = self.rows.get_mut(at.y).unwrap();
@@ -29,51 +87,155 @@ impl Document {
             row.delete(at.x);
         }
         self.unhighlight_rows(at.y);
-        // self.dirty = true;
+        self.dirty = true;
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
+
+    /// Position just past the last character of the document.
+    pub fn end_position(&self) -> Position {
+        let y = self.rows.len().saturating_sub(1);
+        let x = self.rows.get(y).map_or(0, Row::len);
+        Position { x, y }
+    }
+
+    /// A read-only buffer listing `hits` as `path:line:text`.
+    pub fn from_hits(hits: Vec<Hit>) -> Self {
+        let rows = hits
+            .iter()
+            .map(|hit| {
+                Row::from(
+                    format!(
+                        "{}:{}:{}",
+                        hit.path,
+                        hit.position.y.saturating_add(1),
+                        hit.text
+                    )
+                    .as_str(),
+                )
+            })
+            .collect();
+        Self {
+            rows,
+            hits,
+            ..Self::default()
+        }
+    }
+
+    /// The search hit listed in row `y` of a results buffer.
+    pub fn hit(&self, y: usize) -> Option<&Hit> {
+        self.hits.get(y)
+    }
+
+    /// The name shown for the document: its file name, if it has one.
+    pub fn name(&self) -> &str {
+        match &self.filename {
+            Some(filename) => filename,
+            None if !self.hits.is_empty() => "[Grep results]",
+            None => "[No name]",
+        }
     }
 
     pub fn file_type(&self) -> String {
         self.file_type.name()
     }
 
-    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
-        if at.y >= self.rows.len() {
-            return None;
-        }
-        let mut position = Position { x: at.x, y: at.y };
-        let start = if direction == SearchDirection::Forward {
-            at.y
-        } else {
-            0
-        };
-        let end = if direction == SearchDirection::Forward {
-            self.rows.len()
+    pub fn find(
+        &self,
+        query: &Query,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<Position> {
+        self.find_match(query, at, direction)
+            .map(|(start, _)| start)
+    }
+
+    /// Where each match of `query` starts and ends, in document order.
+    pub fn find_all(&self, query: &Query) -> Vec<(Position, Position)> {
+        let (text, starts) = self.joined();
+        query
+            .find_iter(text)
+            .map(|(start, end)| self.match_range(starts, start, end))
+            .collect()
+    }
+
+    /// Finds the next match of `query` from `at` in `direction`, returning
+    /// where it starts and ends. Matches may span rows.
+    pub fn find_match(
+        &self,
+        query: &Query,
+        at: &Position,
+        direction: SearchDirection,
+    ) -> Option<(Position, Position)> {
+        let (text, starts) = self.joined();
+        let at = self.offset(starts, at)?;
+        let (start, end) = if direction == SearchDirection::Forward {
+            query.find_at(text, at)?
         } else {
-            at.y.saturating_add(1)
+            query
+                .find_iter(text)
+                .take_while(|(start, _)| *start < at)
+                .last()?
         };
+        Some(self.match_range(starts, start, end))
+    }
 
-        for _ in start..end {
-            if let Some(row) = self.rows.get(position.y) {
-                if let Some(x) = row.find(query, position.x, direction) {
-                    position.x = x;
-                    return Some(position);
-                }
-                if direction == SearchDirection::Forward {
-                    position.y = position.y.saturating_add(1);
-                    position.x = 0;
-                } else {
-                    position.y = position.y.saturating_sub(1);
-                    position.x = self.rows[position.y].len();
+    /// The text replacing the match of `query` at `at`, as given by
+    /// `template`.
+    pub fn expand_match(&self, query: &Query, at: &Position, template: &str) -> String {
+        let (text, starts) = self.joined();
+        self.offset(starts, at)
+            .map(|at| query.expand(text, at, template))
+            .unwrap_or_default()
+    }
+
+    /// The rows joined by newlines, and the byte offset where each starts.
+    /// They are joined again after an edit.
+    fn joined(&self) -> (&str, &[usize]) {
+        let (text, starts) = self.joined.get_or_init(|| {
+            let mut text = String::new();
+            let mut starts = Vec::with_capacity(self.rows.len());
+            for (y, row) in self.rows.iter().enumerate() {
+                if y > 0 {
+                    text.push('\n');
                 }
-            } else {
-                return None;
+                starts.push(text.len());
+                text.push_str(row.as_str());
             }
+            (text, starts)
+        });
+        (text, starts)
+    }
+
+    /// The byte offset of `at` in the joined rows. Past the end of a row is
+    /// the start of the next one, and past the end of the document is none.
+    fn offset(&self, starts: &[usize], at: &Position) -> Option<usize> {
+        let row = self.rows.get(at.y)?;
+        if at.x <= row.len() {
+            Some(starts[at.y] + row.byte_index(at.x))
+        } else {
+            starts.get(at.y.saturating_add(1)).copied()
         }
-        None
     }
 
-    pub fn highlight(&mut self, word: &Option<String>, until: Option<usize>) {
-        let mut start_with_comments = false;
+    /// Converts a range of bytes in the joined rows to positions.
+    fn match_range(&self, starts: &[usize], start: usize, end: usize) -> (Position, Position) {
+        let position = |byte: usize, round_up: bool| {
+            let y = starts.partition_point(|&row_start| row_start <= byte) - 1;
+            let byte = byte - starts[y];
+            let (first, last) = self.rows[y].grapheme_range(byte, byte);
+            Position {
+                x: if round_up { last } else { first },
+                y,
+            }
+        };
+        (position(start, false), position(end, true))
+    }
+
+    pub fn highlight(&mut self, query: Option<&Query>, until: Option<usize>) {
+        let mut open = None;
         let until = if let Some(until) = until {
             if until.saturating_add(1) < self.rows.len() {
                 until.saturating_add(1)
@@ -83,23 +245,59 @@ impl Document {
         } else {
             self.rows.len()
         };
-        for row in &mut self.rows[..until] {
-            start_with_comments = row.highlight(
+        if let Some(query) = query {
+            if self.matches.as_ref().is_none_or(|(last, _)| last != query) {
+                self.matches = Some((query.clone(), self.row_matches(query)));
+            }
+        }
+        let matches = query.and(self.matches.as_ref());
+        for (y, row) in self.rows[..until].iter_mut().enumerate() {
+            open = row.highlight(
                 self.file_type.highlight_options(),
-                word,
-                start_with_comments,
+                matches.map_or(&[], |(_, matches)| matches[y].as_slice()),
+                open,
             );
         }
     }
 
+    /// The ranges of graphemes each row has in matches of `query`.
+    fn row_matches(&self, query: &Query) -> RowMatches {
+        let mut matches = vec![Vec::new(); self.rows.len()];
+        for (start, end) in self.find_all(query) {
+            let rows = matches.iter_mut().enumerate();
+            for (y, ranges) in rows.take(end.y.saturating_add(1)).skip(start.y) {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y {
+                    end.x
+                } else {
+                    self.rows[y].len()
+                };
+                ranges.push((from, to));
+            }
+        }
+        matches
+    }
+
     pub fn insert(&mut self, at: &Position, c: char) {
-        if at.y > self.len() {
+        if at.y > self.len() || !self.hits.is_empty() {
             return;
         }
         self.dirty = true;
+        self.history.push(Edit {
+            at: at.clone(),
+            removed: String::new(),
+            inserted: c.to_string(),
+        });
         if c == '\n' {
             self.insert_newline(at);
-        } else if at.y == self.rows.len() {
+            return;
+        }
+        let next = Position {
+            x: at.x.saturating_add(1),
+            y: at.y,
+        };
+        self.marks.adjust(at, at, &next);
+        if at.y == self.rows.len() {
             let mut row = Row::default();
             row.insert(0, c);
             self.rows.push(row);
@@ -107,20 +305,52 @@ impl Document {
             let row = self.rows.get_mut(at.y).unwrap();
             row.insert(at.x, c);
         }
+        self.unhighlight_rows(at.y);
     }
 
     pub fn insert_newline(&mut self, at: &Position) {
-        if at.y > self.len() {
+        if at.y > self.len() || !self.hits.is_empty() {
             return;
         }
         self.dirty = true;
+        let next = Position {
+            x: 0,
+            y: at.y.saturating_add(1),
+        };
+        self.marks.adjust(at, at, &next);
         if at.y == self.len() {
             self.rows.push(Row::default());
-            return;
+        } else {
+            let current_row = &mut self.rows[at.y];
+            let new_row = current_row.split(at.x);
+            self.rows.insert(at.y + 1, new_row);
         }
-        let current_row = &mut self.rows[at.y];
-        let new_row = current_row.split(at.x);
-        self.rows.insert(at.y + 1, new_row);
+        self.unhighlight_rows(at.y);
+    }
+
+    /// Inserts `text`, which may span several lines, and returns the position
+    /// just past it.
+    fn insert_text(&mut self, at: &Position, text: &str) -> Position {
+        if at.y == self.rows.len() {
+            self.rows.push(Row::default());
+        }
+        let tail = self.rows[at.y].split(at.x);
+        let mut end = at.clone();
+        for (index, line) in text.split('\n').enumerate() {
+            let line = Row::from(line);
+            if index == 0 {
+                end.x = at.x.saturating_add(line.len());
+                self.rows[at.y].append(&line);
+            } else {
+                end.y = end.y.saturating_add(1);
+                end.x = line.len();
+                self.rows.insert(end.y, line);
+            }
+        }
+        self.rows[end.y].append(&tail);
+        self.unhighlight_rows(at.y);
+        self.marks.adjust(at, at, &end);
+        end
     }
 
     pub fn is_dirty(&self) -> bool {
@@ -135,42 +365,375 @@ impl Document {
         self.rows.len()
     }
 
+    pub fn line_comments(&self) -> &[String] {
+        self.file_type.line_comments()
+    }
+
+    pub fn max_line_length(&self) -> usize {
+        self.config
+            .max_line_length
+            .unwrap_or(DEFAULT_MAX_LINE_LENGTH)
+    }
+
+    /// The text that indents from the cursor at `at` to the next indentation
+    /// level: a tab, or spaces if the `.editorconfig` asks for them.
+    pub fn indentation(&self, at: &Position) -> String {
+        if self.config.indent_style != Some(IndentStyle::Space) {
+            return "\t".to_string();
+        }
+        let size = self.config.indent_size.unwrap_or(1).max(1);
+        let column = self
+            .rows
+            .get(at.y)
+            .map_or(0, |row| row.render_x(at.x, self.tab_width()));
+        " ".repeat(size - column % size)
+    }
+
     pub fn open(filename: &str) -> Result<Self, std::io::Error> {
-        let contents = fs::read_to_string(filename)?;
+        let config = editorconfig::properties(Path::new(filename));
+        let bytes = fs::read(filename)?;
+        let bom = bytes.starts_with(UTF8_BOM);
+        let contents = config.charset.unwrap_or_default().decode(bytes)?;
         let file_type = FileType::from(filename);
         let mut rows = Vec::new();
-        for line in contents.lines() {
-            rows.push(Row::from(line));
+        if let Some(LineEnding::Cr) = config.end_of_line {
+            let contents = contents.strip_suffix('\r').unwrap_or(&contents);
+            rows.extend(contents.split('\r').map(Row::from));
+        } else {
+            rows.extend(contents.lines().map(Row::from));
         }
         Ok(Self {
             dirty: false,
+            config,
             file_type,
             filename: Some(filename.to_string()),
+            history: UndoStack::default(),
+            hits: Vec::new(),
+            marks: Marks::default(),
+            matches: None,
+            joined: OnceCell::new(),
+            bom,
             rows,
         })
     }
 
+    pub fn marks(&mut self) -> &mut Marks {
+        &mut self.marks
+    }
+
+    /// Finds the bracket matching the one at `at`, searching across rows.
+    pub fn matching_bracket(&self, at: &Position) -> Option<Position> {
+        const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+        let c = self.rows.get(at.y)?.char_at(at.x)?;
+        let (open, close, forward) = PAIRS.iter().find_map(|(open, close)| {
+            if c == *open {
+                Some((*open, *close, true))
+            } else if c == *close {
+                Some((*close, *open, false))
+            } else {
+                None
+            }
+        })?;
+        let mut depth = 0_usize;
+        let mut position = at.clone();
+        loop {
+            if let Some(c) = self.rows.get(position.y)?.char_at(position.x) {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(position);
+                    }
+                }
+            }
+            if forward {
+                let width = self.rows.get(position.y)?.len();
+                if position.x + 1 < width {
+                    position.x += 1;
+                } else {
+                    position.y += 1;
+                    position.x = 0;
+                }
+            } else if position.x > 0 {
+                position.x -= 1;
+            } else {
+                position.y = position.y.checked_sub(1)?;
+                position.x = self.rows.get(position.y)?.len().saturating_sub(1);
+            }
+        }
+    }
+
+    fn next_position(&self, at: &Position) -> Position {
+        let width = self.rows.get(at.y).map_or(0, Row::len);
+        if at.x < width {
+            Position {
+                x: at.x.saturating_add(1),
+                y: at.y,
+            }
+        } else if at.y.saturating_add(1) < self.rows.len() {
+            Position {
+                x: 0,
+                y: at.y.saturating_add(1),
+            }
+        } else {
+            at.clone()
+        }
+    }
+
+    pub fn redo(&mut self) -> Option<Position> {
+        let group = self.history.pop_redo()?;
+        let mut end = None;
+        for edit in &group {
+            let removed_end = position_after(&edit.at, &edit.removed);
+            self.remove_text(&edit.at, &removed_end);
+            end = Some(self.insert_text(&edit.at, &edit.inserted));
+        }
+        self.dirty = true;
+        end
+    }
+
+    /// Removes the text between `start` and `end` and returns it.
+    fn remove_text(&mut self, start: &Position, end: &Position) -> String {
+        if start.y >= self.rows.len() {
+            return String::new();
+        }
+        let end = self.clamp(end);
+        let removed = self.text(start, &end);
+        let tail = self.rows[end.y].split(end.x);
+        self.rows[start.y].split(start.x);
+        self.rows.drain(start.y.saturating_add(1)..=end.y);
+        self.rows[start.y].append(&tail);
+        self.unhighlight_rows(start.y);
+        self.marks.adjust(start, &end, start);
+        removed
+    }
+
+    /// Replaces the text between `start` and `end` with `text` as a single
+    /// undoable edit, returning the position just past the inserted text.
+    pub fn replace(&mut self, start: &Position, end: &Position, text: &str) -> Position {
+        if !self.hits.is_empty() {
+            return start.clone();
+        }
+        let removed = self.remove_text(start, end);
+        let inserted_end = self.insert_text(start, text);
+        self.history.push(Edit {
+            at: start.clone(),
+            removed,
+            inserted: text.to_string(),
+        });
+        self.dirty = true;
+        inserted_end
+    }
+
     pub fn row(&self, index: usize) -> Option<&Row> {
         self.rows.get(index)
     }
 
+    /// Writes the document with the line endings and charset its
+    /// `.editorconfig` asks for, trimming trailing whitespace and adding or
+    /// leaving out the final newline if it says to.
     pub fn save(&mut self) -> Result<(), Error> {
-        if let Some(filename) = &self.filename {
-            let mut file = fs::File::create(filename)?;
-            self.file_type = FileType::from(filename);
-            for row in &mut self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
-            }
-            self.dirty = false;
+        let Some(filename) = self.filename.clone() else {
+            return Ok(());
+        };
+        self.file_type = FileType::from(&filename);
+        self.config = editorconfig::properties(Path::new(&filename));
+        let newline = self.config.end_of_line.unwrap_or_default().as_str();
+        // Trailing whitespace is only left out of the file, so the rows, the
+        // cursor and the undo history stay as the user left them.
+        let trim = self.config.trim_trailing_whitespace;
+        let mut contents = self
+            .rows
+            .iter()
+            .map(|row| {
+                if trim {
+                    row.as_str().trim_end()
+                } else {
+                    row.as_str()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(newline);
+        if !self.rows.is_empty() && self.config.insert_final_newline != Some(false) {
+            contents.push_str(newline);
         }
+        let charset = match self.config.charset {
+            Some(charset) => charset,
+            None if self.bom => Charset::Utf8Bom,
+            None => Charset::Utf8,
+        };
+        let bytes = charset.encode(&contents)?;
+        fs::write(filename, bytes)?;
+        self.dirty = false;
         Ok(())
     }
 
+    pub fn tab_width(&self) -> usize {
+        self.config.tab_width.unwrap_or(DEFAULT_TAB_WIDTH).max(1)
+    }
+
+    /// Returns the text between `start` and `end`, joining rows with `\n`.
+    pub fn text(&self, start: &Position, end: &Position) -> String {
+        let end = self.clamp(end);
+        let mut text = String::new();
+        for y in start.y..=end.y {
+            if let Some(row) = self.rows.get(y) {
+                let from = if y == start.y { start.x } else { 0 };
+                let to = if y == end.y { end.x } else { row.len() };
+                text.push_str(&row.slice(from, to));
+                if y != end.y {
+                    text.push('\n');
+                }
+            }
+        }
+        text
+    }
+
+    pub fn undo(&mut self) -> Option<Position> {
+        let group = self.history.pop_undo()?;
+        for edit in group.iter().rev() {
+            let end = position_after(&edit.at, &edit.inserted);
+            self.remove_text(&edit.at, &end);
+            self.insert_text(&edit.at, &edit.removed);
+        }
+        self.dirty = true;
+        group.first().map(|edit| edit.at.clone())
+    }
+
     fn unhighlight_rows(&mut self, start: usize) {
+        self.matches = None;
+        self.joined.take();
         let start = start.saturating_sub(1);
         for row in self.rows.iter_mut().skip(start) {
             row.is_highlighted = false;
         }
     }
 }
+
+fn position_after(at: &Position, text: &str) -> Position {
+    let mut end = at.clone();
+    for (index, line) in text.split('\n').enumerate() {
+        let len = line.graphemes(true).count();
+        if index == 0 {
+            end.x = at.x.saturating_add(len);
+        } else {
+            end.y = end.y.saturating_add(1);
+            end.x = len;
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Options;
+
+    type Ranges = Vec<((usize, usize), (usize, usize))>;
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::default();
+        document.replace(&Position::default(), &Position::default(), text);
+        document
+    }
+
+    fn regex(pattern: &str) -> Query {
+        let options = Options {
+            regex: true,
+            ..Options::default()
+        };
+        Query::new(pattern, options).unwrap()
+    }
+
+    fn found(document: &Document, query: &Query) -> Ranges {
+        document
+            .find_all(query)
+            .into_iter()
+            .map(|(start, end)| ((start.x, start.y), (end.x, end.y)))
+            .collect()
+    }
+
+    #[test]
+    fn finds_matches_spanning_rows() {
+        let document = document("foo\nbar\néé\néa");
+        let query = regex(r"o\nb|é\né");
+        assert_eq!(
+            found(&document, &query),
+            [((2, 0), (1, 1)), ((1, 2), (1, 3))]
+        );
+        let (start, end) = document
+            .find_match(&query, &Position { x: 3, y: 0 }, SearchDirection::Forward)
+            .unwrap();
+        assert_eq!(((start.x, start.y), (end.x, end.y)), ((1, 2), (1, 3)));
+        assert_eq!(
+            document.row_matches(&query),
+            [vec![(2, 3)], vec![(0, 1)], vec![(1, 2)], vec![(0, 1)]]
+        );
+    }
+
+    #[test]
+    fn searches_the_text_as_edited() {
+        let mut document = document("foo\nbar");
+        let query = regex(r"o\nx|oz");
+        assert!(found(&document, &query).is_empty());
+        document.insert(&Position { x: 0, y: 1 }, 'x');
+        assert_eq!(found(&document, &query), [((2, 0), (1, 1))]);
+        document.delete(&Position { x: 3, y: 0 });
+        assert!(found(&document, &query).is_empty());
+        document.insert(&Position { x: 3, y: 0 }, 'z');
+        assert_eq!(found(&document, &query), [((2, 0), (4, 0))]);
+        document.insert(&Position { x: 3, y: 0 }, '\n');
+        assert!(found(&document, &query).is_empty());
+        document.undo();
+        assert_eq!(found(&document, &query), [((2, 0), (4, 0))]);
+    }
+
+    #[test]
+    fn highlights_matches_again_after_an_edit() {
+        let mut document = document("ab\nab");
+        let query = regex("b");
+        document.highlight(Some(&query), None);
+        assert_eq!(
+            document.matches.as_ref().map(|(_, rows)| rows.clone()),
+            Some(vec![vec![(1, 2)], vec![(1, 2)]])
+        );
+        document.insert(&Position { x: 0, y: 0 }, 'b');
+        assert!(document.matches.is_none());
+        document.highlight(Some(&query), None);
+        assert_eq!(
+            document.matches.as_ref().map(|(_, rows)| rows.clone()),
+            Some(vec![vec![(0, 1), (2, 3)], vec![(1, 2)]])
+        );
+    }
+
+    /// Opens and saves a file holding `bytes` next to an `.editorconfig`
+    /// with `properties`, returning what was written.
+    fn saved(name: &str, bytes: &[u8], properties: &str) -> Vec<u8> {
+        let dir = std::env::temp_dir().join(format!("chewol-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let editorconfig = format!("root = true\n[*]\n{}\n", properties);
+        fs::write(dir.join(".editorconfig"), editorconfig).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, bytes).unwrap();
+        let mut document = Document::open(path.to_str().unwrap()).unwrap();
+        document.save().unwrap();
+        let written = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        written
+    }
+
+    #[test]
+    fn keeps_a_byte_order_mark() {
+        assert_eq!(saved("bom", b"\xef\xbb\xbfa\n", ""), b"\xef\xbb\xbfa\n");
+        assert_eq!(saved("no-bom", b"a\n", ""), b"a\n");
+        assert_eq!(
+            saved("utf-8", b"\xef\xbb\xbfa\n", "charset = utf-8"),
+            b"a\n"
+        );
+        assert_eq!(
+            saved("utf-8-bom", b"a\n", "charset = utf-8-bom"),
+            b"\xef\xbb\xbfa\n"
+        );
+    }
+}
@@ -1,9 +1,28 @@
+use crate::completion;
+use crate::fuzzy;
+use crate::grep;
+use crate::history::{self, History};
+use crate::keymap::{Binding, Keymap};
+use crate::keys;
+use crate::reflow;
+use crate::replace::Replacement;
+use crate::search::{Options, Query};
+use crate::shell;
+use crate::snippets::{self, Snippets};
+use crate::syntax;
+use crate::terminal::Event;
+use crate::vim::{self, Command, Operator, Parsed, Register, Vim};
 use crate::Document;
+use crate::Macros;
 use crate::Row;
 use crate::Terminal;
 
+use std::collections::VecDeque;
 use std::env;
+use std::fs;
 use std::io::stdout;
+use std::iter;
+use std::path::Path;
 use std::time::{Duration, Instant};
 use termion::color;
 use termion::event::Key;
@@ -11,9 +30,72 @@ use termion::raw::IntoRawMode;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const QUIT_TIMES: u8 = 2;
+const MACRO_REPLAY_LIMIT: usize = 10_000;
+/// Idle time before dirty buffers are saved when `--auto-save` is given
+/// without a number of seconds.
+const AUTO_SAVE_DELAY: Duration = Duration::from_secs(30);
 
 const STATUS_BAR_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
 const STATUS_BAR_BG_COLOR: color::Rgb = color::Rgb(239, 239, 239);
+const POPUP_FG_COLOR: color::Rgb = color::Rgb(63, 63, 63);
+const POPUP_BG_COLOR: color::Rgb = color::Rgb(215, 215, 215);
+const POPUP_SELECTED_BG_COLOR: color::Rgb = color::Rgb(175, 200, 230);
+const POPUP_HEIGHT: usize = 8;
+
+type CommandFn = fn(&mut Editor);
+
+/// Every command that can be bound in the keymap or run by name.
+const COMMANDS: &[(&str, CommandFn)] = &[
+    ("cursor-up", |editor| editor.move_cursor(Key::Up)),
+    ("cursor-down", |editor| editor.move_cursor(Key::Down)),
+    ("cursor-left", |editor| editor.move_cursor(Key::Left)),
+    ("cursor-right", |editor| editor.move_cursor(Key::Right)),
+    ("page-up", |editor| editor.move_cursor(Key::PageUp)),
+    ("page-down", |editor| editor.move_cursor(Key::PageDown)),
+    ("line-start", |editor| editor.move_cursor(Key::Home)),
+    ("line-end", |editor| editor.move_cursor(Key::End)),
+    ("delete-backward", Editor::delete_backward),
+    ("delete-forward", |editor| {
+        editor.document.delete(&editor.cursor_position);
+    }),
+    ("undo", Editor::undo),
+    ("redo", Editor::redo),
+    ("search", Editor::search),
+    ("clear-highlights", Editor::clear_highlights),
+    ("grep", Editor::grep),
+    ("open-file", Editor::open_file_picker),
+    ("goto", Editor::goto),
+    ("set-mark", Editor::set_mark),
+    ("goto-mark", Editor::goto_mark),
+    ("match-bracket", Editor::match_bracket),
+    ("jump-back", Editor::jump_back),
+    ("jump-forward", Editor::jump_forward),
+    ("save", Editor::save),
+    ("quit", Editor::quit),
+    ("execute-command", Editor::execute_command),
+    ("complete", Editor::complete),
+    ("snippet-next", Editor::snippet_next),
+    ("snippet-previous", |editor| {
+        if let Some(index) = editor
+            .snippet
+            .as_ref()
+            .and_then(|s| s.current.checked_sub(1))
+        {
+            editor.goto_snippet_stop(index);
+        }
+    }),
+    ("next-buffer", Editor::next_buffer),
+    ("previous-buffer", Editor::previous_buffer),
+    ("replace", Editor::replace),
+    ("filter", Editor::filter),
+    ("reflow", Editor::reflow),
+    ("toggle-modal-editing", Editor::toggle_modal_editing),
+    ("toggle-macro-recording", Editor::toggle_macro_recording),
+    ("replay-macro", |editor| editor.replay_macro(1)),
+    ("replay-macro-repeatedly", Editor::replay_macro_repeatedly),
+    ("save-macro", Editor::save_macro),
+    ("load-macro", Editor::load_macro),
+];
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum SearchDirection {
@@ -35,6 +117,51 @@ impl StatusMessage {
     }
 }
 
+/// A document that is open but not being edited, with the view it had.
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+}
+
+/// The files offered by the file picker, and those matching what was typed.
+struct Picker {
+    files: Vec<String>,
+    /// The matching files, best match first.
+    matches: Vec<String>,
+    selected: usize,
+}
+
+impl Picker {
+    fn filter(&mut self, pattern: &str) {
+        let mut scored: Vec<_> = self
+            .files
+            .iter()
+            .filter_map(|path| Some((fuzzy::score(pattern, path)?, path)))
+            .collect();
+        scored.sort_by_key(|(score, path)| (std::cmp::Reverse(*score), path.len()));
+        self.matches = scored.into_iter().map(|(_, path)| path.clone()).collect();
+        self.selected = 0;
+    }
+}
+
+struct Completion {
+    candidates: Vec<String>,
+    selected: usize,
+    /// Column of the start of the word being completed.
+    column: usize,
+}
+
+/// A snippet being filled in, whose tab stops are ranges tracked by the
+/// document's marks.
+struct ActiveSnippet {
+    /// The indices of the marks ranges of each stop, in the order visited.
+    stops: Vec<Vec<usize>>,
+    current: usize,
+    /// The placeholder of the stop just moved to, which typing replaces.
+    placeholder: Option<(Position, Position)>,
+}
+
 #[derive(Default, Clone)]
 pub struct Position {
     pub x: usize,
@@ -42,59 +169,288 @@ pub struct Position {
 }
 
 pub struct Editor {
+    /// How long to wait after the last key press before saving, if at all.
+    /// Losing focus saves as well, but only when this is set: without
+    /// `--auto-save` the editor never saves by itself.
+    auto_save: Option<Duration>,
+    /// Whether an auto-save was attempted since the last key press.
+    auto_saved: bool,
+    buffers: Vec<Buffer>,
+    chord: Vec<Key>,
+    completion: Option<Completion>,
     cursor_position: Position,
     document: Document,
+    history: History,
+    keymap: Keymap,
+    last_keypress: Instant,
+    macros: Macros,
     offset: Position,
+    pending_keys: VecDeque<Key>,
+    picker: Option<Picker>,
+    /// Shown after the input of a prompt, for the callback to report on it.
+    prompt_hint: String,
     quit_times: u8,
+    selection_anchor: Option<Position>,
     should_quit: bool,
+    snippet: Option<ActiveSnippet>,
+    snippets: Snippets,
     status_message: StatusMessage,
     terminal: Terminal,
-    highlighted_word: Option<String>,
+    highlighted_query: Option<Query>,
+    replays_left: usize,
+    search_options: Options,
+    vim: Option<Vim>,
 }
 
 impl Editor {
     pub fn default() -> Self {
-        let args: Vec<String> = env::args().collect();
-        let mut initial_status = String::from("HELP: quit: Ctrl-q | save: Ctrl-s | find: Ctrl-f");
-        let document = if let Some(filename) = args.get(1) {
+        let args: Vec<String> = env::args().skip(1).collect();
+        let modal = args.iter().any(|arg| arg == "--vim");
+        // Everything that went wrong while starting, shown together.
+        let mut errors = Vec::new();
+        let mut auto_save = None;
+        for arg in &args {
+            if arg == "--auto-save" {
+                auto_save = Some(AUTO_SAVE_DELAY);
+            } else if let Some(seconds) = arg.strip_prefix("--auto-save=") {
+                match seconds.parse() {
+                    Ok(seconds) => auto_save = Some(Duration::from_secs(seconds)),
+                    Err(_) => {
+                        errors.push(format!("invalid auto-save delay: {}", seconds));
+                    }
+                }
+            }
+        }
+        let mut documents = Vec::new();
+        for filename in args.iter().filter(|arg| !arg.starts_with("--")) {
             let doc = Document::open(filename);
             if let Ok(doc) = doc {
-                doc
+                documents.push(doc);
             } else {
-                initial_status = format!("ERR: could not open file: {}", filename);
-                Document::default()
+                errors.push(format!("could not open file: {}", filename));
             }
-        } else {
+        }
+        let document = if documents.is_empty() {
             Document::default()
+        } else {
+            documents.remove(0)
         };
+        let buffers = documents
+            .into_iter()
+            .map(|document| Buffer {
+                document,
+                cursor_position: Position::default(),
+                offset: Position::default(),
+            })
+            .collect();
+        let (keymap, keymap_errors) = Keymap::load(is_command);
+        errors.extend(keymap_errors);
+        errors.extend(syntax::errors().iter().cloned());
+        let initial_status = match errors.len() {
+            0 => String::from("HELP: quit: Ctrl-q | save: Ctrl-s | find: Ctrl-f"),
+            1 => format!("ERR: {}", errors[0]),
+            count => format!("ERR: {} problems: {}", count, errors.join("; ")),
+        };
+        let mut terminal = Terminal::default().expect("Failed to initialize terminal");
+        // Losing focus only matters for saving on it.
+        if auto_save.is_some() {
+            terminal.enable_focus_events();
+        }
 
         Self {
+            auto_save,
+            auto_saved: false,
+            buffers,
+            chord: Vec::new(),
+            completion: None,
             cursor_position: Position::default(),
             document,
-            highlighted_word: None,
+            history: History::load(),
+            keymap,
+            highlighted_query: None,
+            last_keypress: Instant::now(),
+            macros: Macros::load(),
             offset: Position::default(),
+            pending_keys: VecDeque::new(),
+            picker: None,
+            prompt_hint: String::new(),
             quit_times: QUIT_TIMES,
+            replays_left: 0,
+            search_options: Options::default(),
+            selection_anchor: None,
             should_quit: false,
+            snippet: None,
+            snippets: Snippets::default(),
             status_message: StatusMessage::from(initial_status),
-            terminal: Terminal::default().expect("Failed to initialize terminal"),
+            terminal,
+            vim: if modal { Some(Vim::default()) } else { None },
+        }
+    }
+
+    /// Saves every dirty buffer that has a file name, reporting the first
+    /// failure in the message bar.
+    fn auto_save(&mut self) {
+        self.auto_saved = true;
+        let mut saved = 0;
+        let mut failure = None;
+        let buffers = self.buffers.iter_mut().map(|buffer| &mut buffer.document);
+        for document in iter::once(&mut self.document).chain(buffers) {
+            let Some(filename) = document.filename.clone() else {
+                continue;
+            };
+            if !document.is_dirty() {
+                continue;
+            }
+            match document.save() {
+                Ok(()) => saved += 1,
+                Err(error) => {
+                    failure
+                        .get_or_insert(format!("ERR: could not auto-save {}: {}", filename, error));
+                }
+            }
+        }
+        if let Some(failure) = failure {
+            self.status_message = StatusMessage::from(failure);
+        } else if saved > 0 {
+            self.status_message = StatusMessage::from(format!("Auto-saved {} file(s).", saved));
+        }
+    }
+
+    /// The display column of `position`, where tabs may span several columns.
+    fn column_of(&self, position: &Position) -> usize {
+        self.document.row(position.y).map_or(position.x, |row| {
+            row.render_x(position.x, self.document.tab_width())
+        })
+    }
+
+    /// Completes the word before the cursor from the words in open buffers,
+    /// showing the candidates in a popup below it.
+    fn complete(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let Some(row) = self.document.row(y) else {
+            return;
+        };
+        let mut start = x;
+        while start > 0 && row.char_at(start - 1).is_some_and(completion::is_word_char) {
+            start -= 1;
+        }
+        let prefix = row.slice(start, x);
+        if prefix.is_empty() {
+            self.status_message = StatusMessage::from("Nothing to complete.".to_string());
+            return;
+        }
+        let candidates = completion::candidates(
+            &prefix,
+            &self.document,
+            &self.cursor_position,
+            self.buffers.iter().map(|buffer| &buffer.document),
+        );
+        if candidates.is_empty() {
+            self.status_message = StatusMessage::from(format!("No completions for `{}`.", prefix));
+            return;
+        }
+        self.completion = Some(Completion {
+            candidates,
+            selected: 0,
+            column: start,
+        });
+        if let Err(error) = self.run_completion(&prefix) {
+            die(&error);
         }
+        self.completion = None;
+    }
+
+    fn draw_completion(&self) {
+        let Some(completion) = &self.completion else {
+            return;
+        };
+        let height = self.terminal.size().height as usize;
+        let items = completion.candidates.len().min(POPUP_HEIGHT);
+        let first = completion.selected.saturating_sub(items - 1);
+        let row = self.cursor_position.y.saturating_sub(self.offset.y);
+        let top = if row + 1 + items <= height {
+            row + 1
+        } else {
+            row.saturating_sub(items)
+        };
+        let width = completion
+            .candidates
+            .iter()
+            .map(|word| word.chars().count())
+            .max()
+            .unwrap_or(0);
+        let x = self
+            .column_of(&Position {
+                x: completion.column,
+                y: self.cursor_position.y,
+            })
+            .saturating_sub(self.offset.x);
+        Terminal::set_fg_color(POPUP_FG_COLOR);
+        for (index, word) in completion
+            .candidates
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(items)
+        {
+            if index == completion.selected {
+                Terminal::set_bg_color(POPUP_SELECTED_BG_COLOR);
+            } else {
+                Terminal::set_bg_color(POPUP_BG_COLOR);
+            }
+            Terminal::cursor_position(&Position {
+                x,
+                y: top + index - first,
+            });
+            print!(" {:width$} ", word, width = width);
+        }
+        Terminal::reset_fg_color();
+        Terminal::reset_bg_color();
+    }
+
+    /// Draws the files matching the file picker's pattern above the status
+    /// bar.
+    fn draw_picker(&self) {
+        let Some(picker) = &self.picker else {
+            return;
+        };
+        let width = self.terminal.size().width as usize;
+        let height = self.terminal.size().height as usize;
+        let items = picker.matches.len().min(POPUP_HEIGHT);
+        let first = picker.selected.saturating_sub(items.saturating_sub(1));
+        Terminal::set_fg_color(POPUP_FG_COLOR);
+        for (index, path) in picker.matches.iter().enumerate().skip(first).take(items) {
+            if index == picker.selected {
+                Terminal::set_bg_color(POPUP_SELECTED_BG_COLOR);
+            } else {
+                Terminal::set_bg_color(POPUP_BG_COLOR);
+            }
+            Terminal::cursor_position(&Position {
+                x: 0,
+                y: height - items + index - first,
+            });
+            let line: String = format!(" {}", path).chars().take(width).collect();
+            print!("{:width$}", line, width = width);
+        }
+        Terminal::reset_fg_color();
+        Terminal::reset_bg_color();
     }
 
     fn draw_message_bar(&self) {
         Terminal::clear_current_line();
         let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
+        if message.time.elapsed() < Duration::new(5, 0) {
             let mut text = message.text.clone();
             text.truncate(self.terminal.size().width as usize);
             print!("{}", text);
         }
     }
 
-    pub fn draw_row(&self, row: &Row) {
+    pub fn draw_row(&self, row: &Row, selected: Option<(usize, usize)>) {
         let width = self.terminal.size().width as usize;
         let start = self.offset.x;
         let end = self.offset.x + width;
-        let row = row.render(start, end);
+        let row = row.render(start, end, selected, self.document.tab_width());
         println!("{}\r", row);
     }
 
@@ -102,8 +458,9 @@ impl Editor {
         let height = self.terminal.size().height;
         for terminal_row in 0..height {
             Terminal::clear_current_line();
-            if let Some(row) = self.document.row(terminal_row as usize + self.offset.y) {
-                self.draw_row(row);
+            let y = terminal_row as usize + self.offset.y;
+            if let Some(row) = self.document.row(y) {
+                self.draw_row(row, self.selected_columns(y));
             } else if self.document.is_empty() && terminal_row == height / 3 {
                 self.draw_welcome_message();
             } else {
@@ -114,14 +471,16 @@ impl Editor {
 
     fn draw_status_bar(&self) {
         let width = self.terminal.size().width as usize;
-        let mut filename = "[No name]".to_string();
+        let mut filename = self.document.name().to_string();
+        filename.truncate(20);
         let modified_indicator = if self.document.is_dirty() { "*" } else { "" };
-        if let Some(name) = &self.document.filename {
-            filename = name.clone();
-            filename.truncate(20);
-        }
+        let mode_indicator = match &self.vim {
+            Some(vim) => format!("-- {} -- ", vim.mode.name()),
+            None => String::new(),
+        };
         let mut status = format!(
-            "{}{} - {} lines",
+            "{}{}{} - {} lines",
+            mode_indicator,
             filename,
             modified_indicator,
             self.document.len()
@@ -157,6 +516,403 @@ impl Editor {
         println!("{}\r", welcome_msg);
     }
 
+    fn delete_backward(&mut self) {
+        if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
+            self.move_cursor(Key::Left);
+            self.document.delete(&self.cursor_position);
+        }
+    }
+
+    /// Stops tracking the tab stops of the active snippet.
+    fn end_snippet(&mut self) {
+        if self.snippet.take().is_some() {
+            self.document.marks().clear_ranges();
+        }
+    }
+
+    /// Runs the command registered as `name`, if any.
+    fn execute(&mut self, name: &str) {
+        if let Some((_, command)) = COMMANDS.iter().find(|(command, _)| *command == name) {
+            command(self);
+        }
+    }
+
+    fn execute_command(&mut self) {
+        let name = self.prompt("Command: ", None, |_, _, _| {}).unwrap_or(None);
+        if let Some(name) = name {
+            if is_command(name.trim()) {
+                self.execute(name.trim());
+            } else {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: unknown command `{}`", name.trim()));
+            }
+        }
+    }
+
+    /// Pipes text through a shell command and replaces it with the output:
+    /// the visual selection in modal editing, which is the only way to select
+    /// text, or else the whole document.
+    fn filter(&mut self) {
+        let command = self
+            .prompt(
+                "Filter through command: ",
+                Some(history::SHELL),
+                |_, _, _| {},
+            )
+            .unwrap_or(None);
+        let Some(command) = command else {
+            return;
+        };
+        let (start, end) = match self.selection_anchor.take() {
+            Some(anchor) => {
+                let (start, end) = vim::ordered(&anchor, &self.cursor_position);
+                let width = self.document.row(end.y).map_or(0, Row::len);
+                let end = Position {
+                    x: end.x.saturating_add(1).min(width),
+                    y: end.y,
+                };
+                (start, end)
+            }
+            None => (Position::default(), self.document.end_position()),
+        };
+        if let Some(vim) = &mut self.vim {
+            vim.mode = vim::Mode::Normal;
+        }
+        let mut input = self.document.text(&start, &end);
+        input.push('\n');
+        match shell::filter(&command, &input) {
+            Ok((output, stderr)) => {
+                let output = output.strip_suffix('\n').unwrap_or(&output);
+                self.document.replace(&start, &end, output);
+                self.cursor_position = start;
+                let mut message = format!(
+                    "Filtered {} lines through `{}`.",
+                    input.lines().count(),
+                    command
+                );
+                if !stderr.is_empty() {
+                    message = format!("{} stderr: {}", message, stderr);
+                }
+                self.status_message = StatusMessage::from(message);
+            }
+            Err(message) => self.status_message = StatusMessage::from(message),
+        }
+    }
+
+    /// Expands the snippet whose trigger is the word before the cursor,
+    /// returning false if the document's file type has no such snippet.
+    fn expand_snippet(&mut self) -> bool {
+        let Position { x, y } = self.cursor_position;
+        let Some(row) = self.document.row(y) else {
+            return false;
+        };
+        let mut start = x;
+        while start > 0 && row.char_at(start - 1).is_some_and(completion::is_word_char) {
+            start -= 1;
+        }
+        let trigger = row.slice(start, x);
+        let indent: String = row
+            .as_str()
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+        if trigger.is_empty() {
+            return false;
+        }
+        let Some(body) = self.snippets.get(&self.document.file_type(), &trigger) else {
+            return false;
+        };
+        let at = Position { x: start, y };
+        let expansion = snippets::expand(body, &at, &indent);
+        self.end_snippet();
+        self.document
+            .replace(&at, &self.cursor_position.clone(), &expansion.text);
+        let mut ranges = Vec::new();
+        let stops = expansion
+            .stops
+            .into_iter()
+            .map(|stop| {
+                stop.into_iter()
+                    .map(|range| {
+                        ranges.push(range);
+                        ranges.len() - 1
+                    })
+                    .collect()
+            })
+            .collect();
+        self.document.marks().set_ranges(ranges);
+        self.snippet = Some(ActiveSnippet {
+            stops,
+            current: 0,
+            placeholder: None,
+        });
+        self.goto_snippet_stop(0);
+        true
+    }
+
+    /// Prompts for a pattern and lists the lines matching it in the files
+    /// under the current directory, in a results buffer of their own.
+    fn grep(&mut self) {
+        let pattern = self
+            .prompt(
+                "Grep (C-r, M-c, M-w toggle options): ",
+                Some(history::GREP),
+                |editor, key, text| {
+                    editor.search_options.toggle(key);
+                    let query = Query::new(text, editor.search_options);
+                    editor.prompt_hint = query_hint(&query, editor.search_options);
+                },
+            )
+            .unwrap_or(None);
+        let Some(pattern) = pattern else {
+            return;
+        };
+        let query = match Query::new(&pattern, self.search_options) {
+            Ok(query) => query,
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("ERR: invalid regex: {}", error));
+                return;
+            }
+        };
+        let (hits, truncated) = grep::search(Path::new("."), &query);
+        if hits.is_empty() {
+            self.status_message = StatusMessage::from(format!("No matches for `{}`.", pattern));
+            return;
+        }
+        let message = if truncated {
+            format!(
+                "Showing the first {} matching lines for `{}`. Enter opens one.",
+                hits.len(),
+                pattern
+            )
+        } else {
+            format!(
+                "{} matching lines for `{}`. Enter opens one.",
+                hits.len(),
+                pattern
+            )
+        };
+        self.show_document(Document::from_hits(hits));
+        // Only the latest results are kept.
+        self.buffers
+            .retain(|buffer| buffer.document.hit(0).is_none());
+        self.status_message = StatusMessage::from(message);
+    }
+
+    /// Opens the file of the search hit under the cursor at the hit.
+    fn open_hit(&mut self) {
+        let Some(hit) = self.document.hit(self.cursor_position.y) else {
+            return;
+        };
+        let (path, position) = (hit.path.clone(), hit.position.clone());
+        if self.open_file(&path) {
+            self.jump_to(&position);
+        }
+    }
+
+    /// Makes the file at `path` the current document, switching to its
+    /// buffer if it is open already. Returns false if it could not be opened.
+    fn open_file(&mut self, path: &str) -> bool {
+        let is_open = |document: &Document| {
+            document.filename.as_ref().is_some_and(|filename| {
+                fs::canonicalize(filename).ok() == fs::canonicalize(path).ok()
+            })
+        };
+        if is_open(&self.document) {
+            return true;
+        }
+        if let Some(index) = self
+            .buffers
+            .iter()
+            .position(|buffer| is_open(&buffer.document))
+        {
+            let mut buffer = self.buffers.remove(index);
+            self.swap_buffer(&mut buffer);
+            self.buffers.push(buffer);
+            return true;
+        }
+        match Document::open(path) {
+            Ok(document) => {
+                self.show_document(document);
+                true
+            }
+            Err(error) => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: could not open {}: {}", path, error));
+                false
+            }
+        }
+    }
+
+    /// Lists the files under the current directory, narrowing them down by
+    /// fuzzy matching as a pattern is typed, and opens the one picked.
+    fn open_file_picker(&mut self) {
+        let mut picker = Picker {
+            files: grep::files(Path::new(".")),
+            matches: Vec::new(),
+            selected: 0,
+        };
+        picker.filter("");
+        self.prompt_hint = format!("  {} files", picker.files.len());
+        self.picker = Some(picker);
+        let pattern = self
+            .prompt_input("Open file (arrows select): ", None, |editor, key, text| {
+                let Some(picker) = &mut editor.picker else {
+                    return;
+                };
+                match key {
+                    Key::Up => picker.selected = picker.selected.saturating_sub(1),
+                    Key::Down => {
+                        if picker.selected + 1 < picker.matches.len() {
+                            picker.selected += 1;
+                        }
+                    }
+                    _ => picker.filter(text),
+                }
+                editor.prompt_hint = format!("  {}/{}", picker.matches.len(), picker.files.len());
+            })
+            .unwrap_or(None);
+        let picker = self.picker.take();
+        let Some(pattern) = pattern else {
+            return;
+        };
+        match picker.and_then(|picker| picker.matches.get(picker.selected).cloned()) {
+            Some(path) => {
+                self.open_file(&path);
+            }
+            None => {
+                self.status_message = StatusMessage::from(format!("No files match `{}`.", pattern));
+            }
+        }
+    }
+
+    /// Makes `document` the current one, keeping the one it replaces as the
+    /// last of the other buffers.
+    fn show_document(&mut self, document: Document) {
+        let mut buffer = Buffer {
+            document,
+            cursor_position: Position::default(),
+            offset: Position::default(),
+        };
+        self.swap_buffer(&mut buffer);
+        self.buffers.push(buffer);
+    }
+
+    /// Prompts for a location and moves the cursor there, centering it.
+    fn goto(&mut self) {
+        let input = self
+            .prompt(
+                "Go to (line[:col], +/-offset or percent%): ",
+                Some(history::GOTO),
+                |_, _, _| {},
+            )
+            .unwrap_or(None);
+        let Some(input) = input else {
+            return;
+        };
+        match parse_location(input.trim(), &self.cursor_position, &self.document) {
+            Ok(position) => self.jump_to(&position),
+            Err(message) => self.status_message = StatusMessage::from(message),
+        }
+    }
+
+    /// Moves to stop `index` of the active snippet, ending the snippet if it
+    /// is the last stop and has no placeholder left to fill in.
+    fn goto_snippet_stop(&mut self, index: usize) {
+        let Some(snippet) = &mut self.snippet else {
+            return;
+        };
+        let Some(&primary) = snippet.stops.get(index).and_then(|ranges| ranges.first()) else {
+            return;
+        };
+        let Some((start, end)) = self.document.marks().range(primary).cloned() else {
+            return;
+        };
+        let is_empty = (start.x, start.y) == (end.x, end.y);
+        snippet.current = index;
+        snippet.placeholder = if is_empty {
+            None
+        } else {
+            Some((start.clone(), end))
+        };
+        let is_last = index + 1 == snippet.stops.len();
+        self.cursor_position = start;
+        self.scroll();
+        if is_last && is_empty {
+            self.end_snippet();
+        }
+    }
+
+    fn goto_mark(&mut self) {
+        let name = self
+            .prompt("Go to mark: ", None, |_, _, _| {})
+            .unwrap_or(None);
+        let Some(name) = name else {
+            return;
+        };
+        match self.document.marks().get(name.trim()).cloned() {
+            Some(position) => self.jump_to(&position),
+            None => {
+                self.status_message =
+                    StatusMessage::from(format!("ERR: no mark named `{}`", name.trim()));
+            }
+        }
+    }
+
+    fn is_replaying(&self) -> bool {
+        !self.pending_keys.is_empty() || self.replays_left > 0
+    }
+
+    fn jump_back(&mut self) {
+        match self.document.marks().jump_back(&self.cursor_position) {
+            Some(position) => self.move_to(&position),
+            None => self.status_message = StatusMessage::from("At start of jump list.".to_string()),
+        }
+    }
+
+    fn jump_forward(&mut self) {
+        match self.document.marks().jump_forward() {
+            Some(position) => self.move_to(&position),
+            None => self.status_message = StatusMessage::from("At end of jump list.".to_string()),
+        }
+    }
+
+    /// Moves the cursor to `position`, recording where it was in the jump
+    /// list, and centers the view on it.
+    fn jump_to(&mut self, position: &Position) {
+        self.document
+            .marks()
+            .push_jump(self.cursor_position.clone());
+        let height = self.terminal.size().height as usize;
+        self.offset.y = position.y.saturating_sub(height / 2);
+        self.move_to(position);
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.document.is_dirty() || self.buffers.iter().any(|buffer| buffer.document.is_dirty())
+    }
+
+    fn load_macro(&mut self) {
+        let name = self
+            .prompt("Load macro: ", None, |_, _, _| {})
+            .unwrap_or(None);
+        if let Some(name) = name {
+            let message = if self.macros.select(&name) {
+                format!("Loaded macro `{}`.", name)
+            } else {
+                format!("ERR: no macro named `{}`", name)
+            };
+            self.status_message = StatusMessage::from(message);
+        }
+    }
+
+    fn match_bracket(&mut self) {
+        match self.document.matching_bracket(&self.cursor_position) {
+            Some(position) => self.jump_to(&position),
+            None => self.status_message = StatusMessage::from("No matching bracket.".to_string()),
+        }
+    }
+
     fn move_cursor(&mut self, key: Key) {
         let Position { mut x, mut y } = self.cursor_position;
         let terminal_height = self.terminal.size().height as usize;
@@ -185,11 +941,7 @@ impl Editor {
             Key::Home | Key::Ctrl('a') => x = 0,
             Key::End | Key::Ctrl('e') => x = width,
             Key::Up => y = y.saturating_sub(1),
-            Key::Down => {
-                if y < height {
-                    y = y.saturating_add(1);
-                }
-            }
+            Key::Down if y < height => y = y.saturating_add(1),
             Key::Left => {
                 // Move cursor left, going up one line if necessary
                 if x > 0 {
@@ -225,92 +977,472 @@ impl Editor {
         self.cursor_position = Position { x, y }
     }
 
+    /// Moves the cursor to `position`, clamped to the document.
+    fn move_to(&mut self, position: &Position) {
+        let y = position.y.min(self.document.len());
+        let width = self.document.row(y).map_or(0, Row::len);
+        self.cursor_position = Position {
+            x: position.x.min(width),
+            y,
+        };
+        self.scroll();
+    }
+
+    fn next_buffer(&mut self) {
+        if self.buffers.is_empty() {
+            self.status_message = StatusMessage::from("No other buffers.".to_string());
+            return;
+        }
+        let mut buffer = self.buffers.remove(0);
+        self.swap_buffer(&mut buffer);
+        self.buffers.push(buffer);
+    }
+
+    /// Applies a modal operator to the text between `start` and `end`, or to
+    /// the whole of rows `lines` when it acts linewise.
+    fn operate(
+        &mut self,
+        operator: Operator,
+        start: Position,
+        end: Position,
+        lines: Option<(usize, usize)>,
+    ) {
+        let (start, end, text) = if let Some((first, last)) = lines {
+            let width = self.document.row(last).map_or(0, Row::len);
+            let content = (Position { x: 0, y: first }, Position { x: width, y: last });
+            let text = format!("{}\n", self.document.text(&content.0, &content.1));
+            if operator == Operator::Change {
+                (content.0, content.1, text)
+            } else {
+                let (start, end) = vim::line_range(&self.document, first, last);
+                (start, end, text)
+            }
+        } else {
+            let text = self.document.text(&start, &end);
+            (start, end, text)
+        };
+        if let Some(vim) = &mut self.vim {
+            vim.register = Register {
+                text,
+                linewise: lines.is_some(),
+            };
+        }
+        match operator {
+            Operator::Yank => (),
+            Operator::Delete => {
+                self.document.replace(&start, &end, "");
+            }
+            Operator::Change => {
+                self.document.replace(&start, &end, "");
+                self.set_modal_mode(vim::Mode::Insert);
+            }
+        }
+        self.cursor_position = match lines {
+            Some((first, _)) if operator == Operator::Delete => {
+                let y = first.min(self.document.len().saturating_sub(1));
+                Position {
+                    x: vim::first_non_blank(&self.document, y),
+                    y,
+                }
+            }
+            Some((first, _)) => Position { x: 0, y: first },
+            None => start,
+        };
+    }
+
+    /// Applies `operator` from the cursor to where `motion` leads.
+    fn operate_to(&mut self, operator: Operator, motion: vim::Motion, count: Option<usize>) {
+        let from = self.cursor_position.clone();
+        let Some((to, motion)) =
+            vim::operator_target(&self.document, &from, operator, motion, count)
+        else {
+            return;
+        };
+        if motion.is_linewise() {
+            let lines = Some((from.y.min(to.y), from.y.max(to.y)));
+            self.operate(operator, from, to, lines);
+        } else {
+            let (start, end) = vim::operator_range(&self.document, &from, &to, motion);
+            self.operate(operator, start, end, None);
+        }
+    }
+
+    fn paste(&mut self, before: bool, times: usize) {
+        let Some(vim) = &self.vim else {
+            return;
+        };
+        let text = vim.register.text.repeat(times);
+        let Position { x, y } = self.cursor_position;
+        let width = self.document.row(y).map_or(0, Row::len);
+        if vim.register.linewise {
+            if before {
+                let at = Position { x: 0, y };
+                self.document.replace(&at, &at, &text);
+                self.cursor_position = at;
+            } else if y.saturating_add(1) < self.document.len() {
+                let at = Position { x: 0, y: y + 1 };
+                self.document.replace(&at, &at, &text);
+                self.cursor_position = at;
+            } else {
+                let at = Position { x: width, y };
+                let text = format!("\n{}", text.strip_suffix('\n').unwrap_or(&text));
+                self.document.replace(&at, &at, &text);
+                self.cursor_position = Position { x: 0, y: y + 1 };
+            }
+        } else {
+            let at = Position {
+                x: if before {
+                    x
+                } else {
+                    x.saturating_add(1).min(width)
+                },
+                y,
+            };
+            let end = self.document.replace(&at, &at, &text);
+            self.cursor_position = Position {
+                x: end.x.saturating_sub(1),
+                y: end.y,
+            };
+        }
+    }
+
+    fn previous_buffer(&mut self) {
+        let Some(mut buffer) = self.buffers.pop() else {
+            self.status_message = StatusMessage::from("No other buffers.".to_string());
+            return;
+        };
+        self.swap_buffer(&mut buffer);
+        self.buffers.insert(0, buffer);
+    }
+
+    /// Feeds `pressed_key` to the keymap, running the command once a bound
+    /// chord is complete. Returns false if the key is not part of any binding.
+    fn process_binding(&mut self, pressed_key: Key) -> bool {
+        self.chord.push(pressed_key);
+        match self.keymap.lookup(&self.chord) {
+            Binding::Command(name) => {
+                let name = name.to_string();
+                self.execute(&name);
+                self.chord.clear();
+            }
+            Binding::Prefix => {
+                self.status_message =
+                    StatusMessage::from(format!("{} -", keys::sequence_to_string(&self.chord)));
+            }
+            Binding::Unbound if self.chord.len() > 1 => {
+                self.status_message = StatusMessage::from(format!(
+                    "ERR: {} is not bound",
+                    keys::sequence_to_string(&self.chord)
+                ));
+                self.chord.clear();
+            }
+            Binding::Unbound => {
+                self.chord.clear();
+                return false;
+            }
+        }
+        true
+    }
+
     fn process_keypress(&mut self) -> Result<(), std::io::Error> {
-        let pressed_key = Terminal::read_key()?;
+        let pressed_key = self.read_key()?;
+        let quit_times = self.quit_times;
+        let placeholder = self
+            .snippet
+            .as_mut()
+            .and_then(|snippet| snippet.placeholder.take());
+        let inserting = self
+            .vim
+            .as_ref()
+            .is_none_or(|vim| vim.mode == vim::Mode::Insert);
         match pressed_key {
-            // Editing
+            Key::Char('\n') if self.document.hit(self.cursor_position.y).is_some() => {
+                self.open_hit();
+            }
+            Key::Backspace if inserting && placeholder.is_some() => {
+                self.replace_placeholder(placeholder);
+            }
+            _ if self.process_modal_key(pressed_key) => (),
+            _ if self.process_binding(pressed_key) => (),
             Key::Char(c) => {
+                self.replace_placeholder(placeholder);
                 self.document.insert(&self.cursor_position, c);
                 self.move_cursor(Key::Right);
             }
-            Key::Delete => self.document.delete(&self.cursor_position),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                    self.move_cursor(Key::Left);
-                    self.document.delete(&self.cursor_position);
-                }
-            }
-
-            // Navigation
-            Key::Up
-            | Key::Down
-            | Key::Left
-            | Key::Right
-            | Key::PageUp
-            | Key::PageDown
-            | Key::Home
-            | Key::End
-            | Key::Ctrl('a' | 'd' | 'e' | 'u') => self.move_cursor(pressed_key),
-
-            // Editor commands
-            Key::Ctrl('f') => self.search(),
-            Key::Ctrl('q') => {
-                if self.quit_times > 0 && self.document.is_dirty() {
-                    self.status_message = StatusMessage::from(format!(
-                        "WARNING! File has unsaved changes. Press Ctrl-Q {} more times to force quit without saving.", self.quit_times
-                    ));
-                    self.quit_times -= 1;
-                    return Ok(());
-                }
-                self.should_quit = true;
-            }
-            Key::Ctrl('s') => self.save(),
             _ => (),
         }
+        self.update_mirrors();
         self.scroll();
-        if self.quit_times < QUIT_TIMES {
+        if self.quit_times < QUIT_TIMES && self.quit_times == quit_times {
             self.quit_times = QUIT_TIMES;
             self.status_message = StatusMessage::from(String::new());
         }
         Ok(())
     }
 
-    fn prompt<C>(&mut self, prompt: &str, mut callback: C) -> Result<Option<String>, std::io::Error>
+    /// Handles `pressed_key` in the modal layer, returning false if it should
+    /// fall through to the regular key bindings.
+    fn process_modal_key(&mut self, pressed_key: Key) -> bool {
+        let Some(vim) = &mut self.vim else {
+            return false;
+        };
+        if vim.mode == vim::Mode::Insert {
+            vim.push_insert(pressed_key);
+            if pressed_key == Key::Esc {
+                vim.finish_change();
+                self.set_modal_mode(vim::Mode::Normal);
+                self.cursor_position.x = self.cursor_position.x.saturating_sub(1);
+                return true;
+            }
+            return false;
+        }
+        match vim.push(pressed_key) {
+            Parsed::Pending | Parsed::Invalid => true,
+            Parsed::Passthrough => false,
+            Parsed::Done(count, command) => {
+                self.run_modal_command(count, command);
+                if let Some(vim) = &mut self.vim {
+                    if vim.mode != vim::Mode::Insert {
+                        vim.finish_change();
+                        let width = self
+                            .document
+                            .row(self.cursor_position.y)
+                            .map_or(0, Row::len);
+                        self.cursor_position.x =
+                            self.cursor_position.x.min(width.saturating_sub(1));
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    /// Reads a line of input in the message bar, calling `callback` after
+    /// every key with the input so far. Returns `None` if the input is empty
+    /// or cancelled. Ctrl-p and Ctrl-n recall the older and newer entries of
+    /// the `history` kind of prompt, if any, to which the input is added.
+    fn prompt<C>(
+        &mut self,
+        prompt: &str,
+        history: Option<&str>,
+        callback: C,
+    ) -> Result<Option<String>, std::io::Error>
+    where
+        C: FnMut(&mut Self, Key, &String),
+    {
+        let result = self.prompt_input(prompt, history, callback)?;
+        Ok(result.filter(|result| !result.is_empty()))
+    }
+
+    /// Like `prompt`, but only returns `None` if the input is cancelled.
+    fn prompt_input<C>(
+        &mut self,
+        prompt: &str,
+        history: Option<&str>,
+        mut callback: C,
+    ) -> Result<Option<String>, std::io::Error>
     where
         C: FnMut(&mut Self, Key, &String),
     {
         let mut result = String::new();
+        let mut cancelled = false;
+        let entries = history.map_or(Vec::new(), |kind| self.history.entries(kind).to_vec());
+        // The entry shown, counting back from the newest, and what was typed
+        // before going back through the history.
+        let mut recalled = 0;
+        let mut draft = String::new();
         loop {
-            self.status_message = StatusMessage::from(format!("{}{}", prompt, result));
-            self.refresh_screen()?;
-            let key = Terminal::read_key()?;
+            // Alt-Enter types a newline, shown as a return symbol.
+            self.status_message = StatusMessage::from(format!(
+                "{}{}{}",
+                prompt,
+                result.replace('\n', "\u{23ce}"),
+                self.prompt_hint
+            ));
+            if !self.is_replaying() {
+                self.refresh_screen()?;
+            }
+            let key = self.read_key()?;
             match key {
                 Key::Backspace => {
-                    if !result.is_empty() {
-                        result.truncate(result.len() - 1);
-                    }
+                    result.pop();
                 }
                 Key::Char('\n') => break,
-                Key::Char(c) => {
-                    if !c.is_control() {
-                        result.push(c);
-                    }
-                }
+                Key::Char(c) if !c.is_control() => result.push(c),
+                Key::Alt('\r' | '\n') => result.push('\n'),
                 Key::Esc => {
-                    result.truncate(0);
+                    cancelled = true;
                     break;
                 }
+                Key::Ctrl('p') if recalled < entries.len() => {
+                    if recalled == 0 {
+                        draft.clone_from(&result);
+                    }
+                    recalled = recalled.saturating_add(1);
+                    result.clone_from(&entries[entries.len() - recalled]);
+                }
+                Key::Ctrl('n') if recalled > 0 => {
+                    recalled = recalled.saturating_sub(1);
+                    result = match recalled {
+                        0 => draft.clone(),
+                        _ => entries[entries.len() - recalled].clone(),
+                    };
+                }
                 _ => (),
             }
             callback(self, key, &result);
         }
+        self.prompt_hint.clear();
         self.status_message = StatusMessage::from(String::new());
-        if result.is_empty() {
+        if cancelled {
             return Ok(None);
         }
+        if let Some(kind) = history.filter(|_| !result.is_empty()) {
+            self.history.add(kind, &result);
+        }
         Ok(Some(result))
     }
 
+    fn quit(&mut self) {
+        if self.quit_times > 0 && self.is_dirty() {
+            self.status_message = StatusMessage::from(format!(
+                "WARNING! Files have unsaved changes. Quit {} more times to force quit without saving.", self.quit_times
+            ));
+            self.quit_times -= 1;
+            return;
+        }
+        self.should_quit = true;
+    }
+
+    /// Returns the next key, taking it from a replaying macro if there is one
+    /// and otherwise from the terminal, where it is recorded if a macro is
+    /// being recorded. While waiting on the terminal, dirty buffers are
+    /// auto-saved once the idle delay passes or the terminal loses focus.
+    fn read_key(&mut self) -> Result<Key, std::io::Error> {
+        if self.pending_keys.is_empty() && self.replays_left > 0 {
+            self.replays_left -= 1;
+            self.pending_keys.extend(self.macros.current());
+        }
+        if let Some(key) = self.pending_keys.pop_front() {
+            return Ok(key);
+        }
+        loop {
+            let timeout = self
+                .auto_save
+                .filter(|_| !self.auto_saved && self.is_dirty())
+                .map(|delay| delay.saturating_sub(self.last_keypress.elapsed()));
+            match self.terminal.read_event(timeout)? {
+                Some(Event::Key(key)) => {
+                    self.auto_saved = false;
+                    self.last_keypress = Instant::now();
+                    self.macros.record(key);
+                    return Ok(key);
+                }
+                Some(Event::FocusOut) if self.auto_save.is_none() => (),
+                Some(Event::FocusOut) | None => {
+                    self.auto_save();
+                    self.refresh_screen()?;
+                }
+            }
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(position) = self.document.redo() {
+            self.cursor_position = position;
+        }
+    }
+
+    /// Rewraps the paragraph at the cursor, or the selected lines, to the
+    /// document's maximum line length.
+    fn reflow(&mut self) {
+        let comments = self.document.line_comments().to_vec();
+        let (first, last) = if let Some(anchor) = self.selection_anchor.take() {
+            self.set_modal_mode(vim::Mode::Normal);
+            let (start, end) = vim::ordered(&anchor, &self.cursor_position);
+            (start.y, end.y)
+        } else if let Some(rows) =
+            reflow::paragraph(&self.document, self.cursor_position.y, &comments)
+        {
+            rows
+        } else {
+            self.status_message = StatusMessage::from("No paragraph to reflow.".to_string());
+            return;
+        };
+        let last = last.min(self.document.len().saturating_sub(1));
+        let lines: Vec<&str> = (first..=last)
+            .filter_map(|y| self.document.row(y))
+            .map(Row::as_str)
+            .collect();
+        let filled = reflow::fill(
+            &lines,
+            &comments,
+            self.document.max_line_length(),
+            self.document.tab_width(),
+        )
+        .join("\n");
+        let start = Position { x: 0, y: first };
+        let end = Position {
+            x: self.document.row(last).map_or(0, Row::len),
+            y: last,
+        };
+        if self.document.text(&start, &end) != filled {
+            self.document.replace(&start, &end, &filled);
+        }
+        self.cursor_position = start;
+    }
+
+    /// Prompts for a pattern and its replacement, then offers to replace
+    /// each match from the cursor on, as one undoable change.
+    fn replace(&mut self) {
+        let pattern = self
+            .prompt(
+                "Replace (C-r, M-c, M-w toggle options): ",
+                Some(history::REPLACE),
+                |editor, key, text| {
+                    editor.search_options.toggle(key);
+                    let query = Query::new(text, editor.search_options);
+                    editor.prompt_hint = query_hint(&query, editor.search_options);
+                    editor.highlighted_query = query.ok();
+                },
+            )
+            .unwrap_or(None);
+        self.highlighted_query = None;
+        let Some(pattern) = pattern else {
+            return;
+        };
+        let query = match Query::new(&pattern, self.search_options) {
+            Ok(query) => query,
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("ERR: invalid regex: {}", error));
+                return;
+            }
+        };
+        let template = self
+            .prompt_input(&format!("Replace `{}` with: ", pattern), None, |_, _, _| {})
+            .unwrap_or(None);
+        let Some(template) = template else {
+            return;
+        };
+        let replaced = self.run_replace(&query, &template);
+        self.highlighted_query = None;
+        match replaced {
+            Ok(count) => {
+                self.status_message =
+                    StatusMessage::from(format!("Replaced {} occurrence(s).", count));
+            }
+            Err(error) => die(&error),
+        }
+    }
+
+    /// Deletes the placeholder that typing at a snippet stop replaces.
+    fn replace_placeholder(&mut self, placeholder: Option<(Position, Position)>) {
+        if let Some((start, end)) = placeholder {
+            self.document.replace(&start, &end, "");
+            self.cursor_position = start;
+        }
+    }
+
     fn refresh_screen(&mut self) -> Result<(), std::io::Error> {
         Terminal::cursor_hide();
         Terminal::cursor_position(&Position::default());
@@ -319,7 +1451,7 @@ impl Editor {
             Terminal::clear_screen();
         } else {
             self.document.highlight(
-                &self.highlighted_word,
+                self.highlighted_query.as_ref(),
                 Some(
                     self.offset
                         .y
@@ -327,10 +1459,18 @@ impl Editor {
                 ),
             );
             self.draw_rows();
+            self.draw_completion();
+            self.draw_picker();
+            Terminal::cursor_position(&Position {
+                x: 0,
+                y: self.terminal.size().height as usize,
+            });
             self.draw_status_bar();
             self.draw_message_bar();
             Terminal::cursor_position(&Position {
-                x: self.cursor_position.x.saturating_sub(self.offset.x),
+                x: self
+                    .column_of(&self.cursor_position)
+                    .saturating_sub(self.offset.x),
                 y: self.cursor_position.y.saturating_sub(self.offset.y),
             });
         }
@@ -341,8 +1481,14 @@ impl Editor {
     pub fn run(&mut self) {
         let _stdout = stdout().into_raw_mode().unwrap();
         loop {
-            if let Err(error) = self.refresh_screen() {
-                die(&error);
+            if self.is_replaying() && !self.should_quit {
+                // Skip drawing the intermediate states of a macro, and
+                // writing the history after each of its prompts.
+            } else {
+                self.save_history();
+                if let Err(error) = self.refresh_screen() {
+                    die(&error);
+                }
             }
             if self.should_quit {
                 break;
@@ -353,12 +1499,218 @@ impl Editor {
         }
     }
 
+    fn replay_macro(&mut self, times: usize) {
+        if self.macros.is_recording() {
+            self.status_message =
+                StatusMessage::from("ERR: cannot replay a macro while recording".to_string());
+        } else if self.macros.current().is_empty() {
+            // Replaying nothing would only count down one replay per key
+            // read from the terminal, holding off redraws meanwhile.
+            self.status_message = StatusMessage::from("ERR: the macro is empty".to_string());
+        } else if !self.is_replaying() {
+            self.replays_left = times;
+        }
+    }
+
+    fn replay_macro_repeatedly(&mut self) {
+        let count = self
+            .prompt(
+                "Replay macro (count, or * until a search fails): ",
+                None,
+                |_, _, _| {},
+            )
+            .unwrap_or(None);
+        match count.as_deref().map(str::trim) {
+            None => (),
+            Some("*") => self.replay_macro(MACRO_REPLAY_LIMIT),
+            Some(count) => match count.parse() {
+                Ok(count) => self.replay_macro(count),
+                Err(_) => {
+                    self.status_message =
+                        StatusMessage::from(format!("ERR: invalid count: {}", count));
+                }
+            },
+        }
+    }
+
+    /// Lets the user pick from the completion popup, inserting the rest of
+    /// the chosen word after `prefix`. Any key the popup doesn't handle
+    /// closes it and is then processed as usual.
+    fn run_completion(&mut self, prefix: &str) -> Result<(), std::io::Error> {
+        loop {
+            if !self.is_replaying() {
+                self.refresh_screen()?;
+            }
+            let key = self.read_key()?;
+            let Some(completion) = &mut self.completion else {
+                return Ok(());
+            };
+            let len = completion.candidates.len();
+            match key {
+                Key::Down | Key::Ctrl('n') => completion.selected = (completion.selected + 1) % len,
+                Key::Up | Key::Ctrl('p') => {
+                    completion.selected = (completion.selected + len - 1) % len;
+                }
+                Key::Char('\n' | '\t') => {
+                    let word = completion.candidates[completion.selected].clone();
+                    for c in word.chars().skip(prefix.chars().count()) {
+                        self.document.insert(&self.cursor_position, c);
+                        self.move_cursor(Key::Right);
+                    }
+                    return Ok(());
+                }
+                Key::Esc => return Ok(()),
+                _ => {
+                    self.pending_keys.push_front(key);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Walks the matches of `query` from the cursor on, asking whether to
+    /// replace each with `template`, and returns how many were replaced.
+    fn run_replace(&mut self, query: &Query, template: &str) -> Result<usize, std::io::Error> {
+        self.highlighted_query = Some(query.clone());
+        let from = self.cursor_position.clone();
+        let mut replacement = Replacement::new(&mut self.document, query, template, from);
+        let confirmed = self.confirm_replacements(&mut replacement);
+        let count = replacement.finish(&mut self.document);
+        confirmed.map(|()| count)
+    }
+
+    /// Asks about each match in turn, replacing those confirmed.
+    fn confirm_replacements(
+        &mut self,
+        replacement: &mut Replacement,
+    ) -> Result<(), std::io::Error> {
+        while let Some((start, end)) = replacement.next_match(&self.document) {
+            self.cursor_position = start.clone();
+            self.scroll();
+            self.status_message =
+                StatusMessage::from("Replace? (y)es, (n)o, (a)ll remaining, (q)uit".to_string());
+            if !self.is_replaying() {
+                self.refresh_screen()?;
+            }
+            match self.read_key()? {
+                Key::Char('y' | ' ') => replacement.replace(&mut self.document, &start, &end),
+                Key::Char('n') | Key::Backspace | Key::Delete => replacement.skip(&end),
+                Key::Char('a' | '!') => {
+                    replacement.replace(&mut self.document, &start, &end);
+                    if let Some(last) = replacement.replace_all(&mut self.document) {
+                        self.cursor_position = last;
+                    }
+                    break;
+                }
+                Key::Char('q' | '\n') | Key::Esc => break,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn run_modal_command(&mut self, count: Option<usize>, command: Command) {
+        let times = count.unwrap_or(1);
+        let Position { x, y } = self.cursor_position;
+        let width = self.document.row(y).map_or(0, Row::len);
+        match command {
+            Command::Move(motion @ (vim::Motion::FirstLine | vim::Motion::LastLine)) => {
+                if let Some(target) =
+                    vim::target(&self.document, &self.cursor_position, motion, count)
+                {
+                    self.jump_to(&target);
+                }
+            }
+            Command::Move(motion) => {
+                if let Some(target) =
+                    vim::target(&self.document, &self.cursor_position, motion, count)
+                {
+                    self.cursor_position = target;
+                }
+            }
+            Command::Operate(operator, motion) => self.operate_to(operator, motion, count),
+            Command::OperateLines(operator) => {
+                let last = y
+                    .saturating_add(times - 1)
+                    .min(self.document.len().saturating_sub(1));
+                self.operate(
+                    operator,
+                    Position::default(),
+                    Position::default(),
+                    Some((y, last)),
+                );
+            }
+            Command::OperateSelection(operator) => {
+                let anchor = self
+                    .selection_anchor
+                    .take()
+                    .unwrap_or_else(|| self.cursor_position.clone());
+                let (start, end) = vim::ordered(&anchor, &self.cursor_position);
+                let end = Position {
+                    x: end.x.saturating_add(1),
+                    y: end.y,
+                };
+                self.set_modal_mode(vim::Mode::Normal);
+                self.operate(operator, start, end, None);
+            }
+            Command::Insert
+            | Command::InsertAfter
+            | Command::InsertLineStart
+            | Command::InsertLineEnd
+            | Command::OpenBelow
+            | Command::OpenAbove => self.start_insert(command),
+            Command::DeleteChar if x < width => {
+                let end = Position {
+                    x: x.saturating_add(times).min(width),
+                    y,
+                };
+                self.operate(Operator::Delete, self.cursor_position.clone(), end, None);
+            }
+            Command::DeleteCharBefore if x > 0 => {
+                let start = Position {
+                    x: x.saturating_sub(times),
+                    y,
+                };
+                self.operate(Operator::Delete, start, self.cursor_position.clone(), None);
+            }
+            Command::PasteAfter => self.paste(false, times),
+            Command::PasteBefore => self.paste(true, times),
+            Command::Undo => (0..times).for_each(|_| self.undo()),
+            Command::Redo => (0..times).for_each(|_| self.redo()),
+            Command::Repeat => {
+                if let Some(vim) = &self.vim {
+                    for _ in 0..times {
+                        for key in vim.last_change().iter().rev() {
+                            self.pending_keys.push_front(*key);
+                        }
+                    }
+                }
+            }
+            Command::Visual => {
+                self.selection_anchor = Some(self.cursor_position.clone());
+                self.set_modal_mode(vim::Mode::Visual);
+            }
+            Command::Escape => {
+                self.selection_anchor = None;
+                self.set_modal_mode(vim::Mode::Normal);
+            }
+            Command::DeleteChar | Command::DeleteCharBefore => (),
+        }
+        let last_line = self.document.len().saturating_sub(1);
+        self.cursor_position.y = self.cursor_position.y.min(last_line);
+        let width = self
+            .document
+            .row(self.cursor_position.y)
+            .map_or(0, Row::len);
+        self.cursor_position.x = self.cursor_position.x.min(width);
+    }
+
     fn save(&mut self) {
         if !self.document.is_dirty() {
             return;
         }
         if self.document.filename.is_none() {
-            let new_name = self.prompt("Save as: ", |_, _, _| {}).unwrap_or(None);
+            let new_name = self.prompt("Save as: ", None, |_, _, _| {}).unwrap_or(None);
             if new_name.is_none() {
                 self.status_message = StatusMessage::from("Save aborted.".to_string());
                 return;
@@ -372,11 +1724,77 @@ impl Editor {
         }
     }
 
+    fn save_history(&mut self) {
+        if let Err(error) = self.history.save() {
+            self.status_message =
+                StatusMessage::from(format!("ERR: could not save history: {}", error));
+        }
+    }
+
+    fn save_macro(&mut self) {
+        let name = self
+            .prompt("Save macro as: ", None, |_, _, _| {})
+            .unwrap_or(None);
+        if let Some(name) = name {
+            if name.trim().is_empty() {
+                self.status_message = StatusMessage::from("ERR: a macro needs a name".to_string());
+                return;
+            }
+            let message = match self.macros.save(name.trim()) {
+                Ok(()) => format!("Saved macro `{}`.", name.trim()),
+                Err(err) => format!("ERR: could not save macro: {}", err),
+            };
+            self.status_message = StatusMessage::from(message);
+        }
+    }
+
+    fn set_mark(&mut self) {
+        let name = self
+            .prompt("Set mark: ", None, |_, _, _| {})
+            .unwrap_or(None);
+        if let Some(name) = name {
+            let name = name.trim();
+            self.document
+                .marks()
+                .set(name, self.cursor_position.clone());
+            self.status_message = StatusMessage::from(format!("Mark `{}` set.", name));
+        }
+    }
+
+    /// The columns of row `y` covered by the visual selection, or by the
+    /// snippet placeholder that typing would replace, if any.
+    fn selected_columns(&self, y: usize) -> Option<(usize, usize)> {
+        let (start, end) = if let Some(anchor) = &self.selection_anchor {
+            let (start, mut end) = vim::ordered(anchor, &self.cursor_position);
+            end.x = end.x.saturating_add(1);
+            (start, end)
+        } else {
+            self.snippet.as_ref()?.placeholder.clone()?
+        };
+        if y < start.y || y > end.y {
+            return None;
+        }
+        let from = if y == start.y { start.x } else { 0 };
+        let to = if y == end.y {
+            end.x
+        } else {
+            self.document.row(y).map_or(0, Row::len)
+        };
+        Some((from, to))
+    }
+
+    fn set_modal_mode(&mut self, mode: vim::Mode) {
+        if let Some(vim) = &mut self.vim {
+            vim.mode = mode;
+        }
+    }
+
     fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
+        let x = self.column_of(&self.cursor_position);
+        let y = self.cursor_position.y;
         let width = self.terminal.size().width as usize;
         let height = self.terminal.size().height as usize;
-        let mut offset = &mut self.offset;
+        let offset = &mut self.offset;
         if y < offset.y {
             offset.y = y;
         } else if y >= offset.y.saturating_add(height) {
@@ -389,13 +1807,26 @@ impl Editor {
         }
     }
 
+    /// Searches as the query is typed. Up or Left and Down or Right step to
+    /// the previous and next match, while Ctrl-p and Ctrl-n recall earlier
+    /// queries.
     fn search(&mut self) {
         let old_position = self.cursor_position.clone();
         let mut direction = SearchDirection::Forward;
+        let mut found = true;
         let query = self
             .prompt(
-                "Search (ESC to cancel, arrows to navigate): ",
-                |editor, key, query| {
+                "Search (C-r, M-c, M-w, M-a, M-h toggle, arrows step): ",
+                Some(history::SEARCH),
+                |editor, key, text| {
+                    editor.search_options.toggle(key);
+                    let query = Query::new(text, editor.search_options);
+                    editor.prompt_hint = query_hint(&query, editor.search_options);
+                    let Ok(query) = query else {
+                        editor.highlighted_query = None;
+                        found = false;
+                        return;
+                    };
                     let mut moved = false;
                     match key {
                         Key::Right | Key::Down => {
@@ -406,32 +1837,315 @@ impl Editor {
                         Key::Left | Key::Up => direction = SearchDirection::Backward,
                         _ => direction = SearchDirection::Forward,
                     }
-                    if let Some(position) =
+                    let mut position =
                         editor
                             .document
-                            .find(query, &editor.cursor_position, direction)
-                    {
+                            .find(&query, &editor.cursor_position, direction);
+                    let mut wrapped = false;
+                    if position.is_none() && editor.search_options.wrap {
+                        let from = match direction {
+                            SearchDirection::Forward => Position::default(),
+                            SearchDirection::Backward => editor.document.end_position(),
+                        };
+                        position = editor.document.find(&query, &from, direction);
+                        wrapped = position.is_some();
+                    }
+                    let matches = editor.document.find_all(&query);
+                    if let Some(index) = position.as_ref().and_then(|position| {
+                        matches
+                            .iter()
+                            .position(|(start, _)| start.x == position.x && start.y == position.y)
+                    }) {
+                        editor.prompt_hint = format!(
+                            "{}  match {} of {}",
+                            editor.prompt_hint,
+                            index.saturating_add(1),
+                            matches.len()
+                        );
+                    }
+                    if wrapped {
+                        editor.prompt_hint.push_str("  search wrapped");
+                    }
+                    if let Some(position) = position {
                         editor.cursor_position = position;
                         editor.scroll();
-                    } else if moved {
-                        editor.move_cursor(Key::Left);
+                        found = true;
+                    } else {
+                        if moved {
+                            editor.move_cursor(Key::Left);
+                        }
+                        found = text.is_empty();
                     }
-                    editor.highlighted_word = Some(query.to_string());
+                    editor.highlighted_query = Some(query);
                 },
             )
             .unwrap_or(None);
         if query.is_none() {
             self.cursor_position = old_position;
             self.scroll();
+        } else if found {
+            self.document.marks().push_jump(old_position);
+        } else if self.is_replaying() {
+            self.stop_replaying("search failed");
+        }
+        if query.is_none() || !self.search_options.keep_highlights {
+            self.highlighted_query = None;
+        }
+    }
+
+    /// Stops highlighting the matches kept from the last search.
+    fn clear_highlights(&mut self) {
+        self.highlighted_query = None;
+    }
+
+    /// Expands the snippet named by the word before the cursor, or moves to
+    /// the next stop of the active snippet, or else inserts a tab.
+    fn snippet_next(&mut self) {
+        if self.expand_snippet() {
+            return;
+        }
+        if let Some(snippet) = &self.snippet {
+            if snippet.current + 1 < snippet.stops.len() {
+                self.goto_snippet_stop(snippet.current + 1);
+                return;
+            }
+        }
+        self.end_snippet();
+        for c in self.document.indentation(&self.cursor_position).chars() {
+            self.document.insert(&self.cursor_position, c);
+            self.move_cursor(Key::Right);
+        }
+    }
+
+    /// Moves the cursor to where an insert command starts typing, opening a
+    /// new line first for `o` and `O`, and switches to insert mode.
+    fn start_insert(&mut self, command: Command) {
+        let Position { x, y } = self.cursor_position;
+        let width = self.document.row(y).map_or(0, Row::len);
+        match command {
+            Command::InsertAfter => self.cursor_position.x = x.saturating_add(1).min(width),
+            Command::InsertLineStart => {
+                self.cursor_position.x = vim::first_non_blank(&self.document, y);
+            }
+            Command::InsertLineEnd => self.cursor_position.x = width,
+            Command::OpenBelow => {
+                let at = Position { x: width, y };
+                self.document.replace(&at, &at, "\n");
+                self.cursor_position = Position { x: 0, y: y + 1 };
+            }
+            Command::OpenAbove => {
+                let at = Position { x: 0, y };
+                self.document.replace(&at, &at, "\n");
+                self.cursor_position = at;
+            }
+            _ => (),
+        }
+        self.set_modal_mode(vim::Mode::Insert);
+    }
+
+    fn stop_replaying(&mut self, reason: &str) {
+        self.pending_keys.clear();
+        self.replays_left = 0;
+        self.status_message = StatusMessage::from(format!("Macro stopped: {}.", reason));
+    }
+
+    fn toggle_modal_editing(&mut self) {
+        self.selection_anchor = None;
+        let message = if self.vim.take().is_some() {
+            "Modal editing off."
+        } else {
+            self.vim = Some(Vim::default());
+            "Modal editing on."
+        };
+        self.status_message = StatusMessage::from(message.to_string());
+    }
+
+    /// Exchanges the current document and its view with `buffer`.
+    fn swap_buffer(&mut self, buffer: &mut Buffer) {
+        self.end_snippet();
+        std::mem::swap(&mut self.document, &mut buffer.document);
+        std::mem::swap(&mut self.cursor_position, &mut buffer.cursor_position);
+        std::mem::swap(&mut self.offset, &mut buffer.offset);
+        self.selection_anchor = None;
+        self.status_message = StatusMessage::from(format!("Switched to {}.", self.document.name()));
+    }
+
+    fn toggle_macro_recording(&mut self) {
+        let message = if self.macros.is_recording() {
+            let len = self.macros.stop_recording(self.chord.len().max(1));
+            format!("Recorded macro of {} keys.", len)
+        } else if self.is_replaying() {
+            return;
+        } else {
+            self.macros.start_recording();
+            "Recording macro... (F3 to stop)".to_string()
+        };
+        self.status_message = StatusMessage::from(message);
+    }
+
+    fn undo(&mut self) {
+        if let Some(position) = self.document.undo() {
+            self.cursor_position = position;
+        }
+    }
+
+    /// Copies the text at the current stop of the active snippet to the stops
+    /// that mirror it, keeping the cursor where it was within that text.
+    fn update_mirrors(&mut self) {
+        let Some(snippet) = &self.snippet else {
+            return;
+        };
+        let ranges = snippet.stops[snippet.current].clone();
+        let marks = self.document.marks();
+        let Some((start, end)) = marks.range(ranges[0]).cloned() else {
+            return;
+        };
+        let text = self.document.text(&start, &end);
+        for &index in &ranges[1..] {
+            let Some((from, to)) = self.document.marks().range(index).cloned() else {
+                continue;
+            };
+            if self.document.text(&from, &to) != text {
+                self.document.replace(&from, &to, &text);
+            }
+        }
+        let Some((moved, _)) = self.document.marks().range(ranges[0]).cloned() else {
+            return;
+        };
+        let cursor = &mut self.cursor_position;
+        if (cursor.y, cursor.x) >= (start.y, start.x) {
+            if cursor.y == start.y {
+                cursor.x = cursor.x - start.x + moved.x;
+            }
+            cursor.y = cursor.y - start.y + moved.y;
         }
-        self.highlighted_word = None;
     }
 }
 
 // ///////////////////////////////
 // Utils
 
+fn is_command(name: &str) -> bool {
+    COMMANDS.iter().any(|(command, _)| *command == name)
+}
+
+/// The hint shown after a search pattern: the search options that are on, or
+/// why it is not a valid regex.
+fn query_hint(query: &Result<Query, String>, options: Options) -> String {
+    match query {
+        Err(error) => format!("  [regex: {}]", error),
+        Ok(_) if options.describe().is_empty() => String::new(),
+        Ok(_) => format!("  [{}]", options.describe()),
+    }
+}
+
+/// Parses a goto location: `line`, `line:col` (both 1-based), a relative line
+/// offset such as `+10` or `-5`, or a percentage of the document such as `50%`.
+fn parse_location(input: &str, from: &Position, document: &Document) -> Result<Position, String> {
+    let invalid = || format!("ERR: invalid location `{}`", input);
+    let len = document.len();
+    let (line, column) = if let Some(percent) = input.strip_suffix('%') {
+        let percent: usize = percent.parse().map_err(|_| invalid())?;
+        if percent > 100 {
+            return Err(format!("ERR: {}% is out of range", percent));
+        }
+        (len.saturating_mul(percent).div_ceil(100).max(1), None)
+    } else if let Some(offset) = input.strip_prefix('+') {
+        let offset: usize = offset.parse().map_err(|_| invalid())?;
+        (from.y.saturating_add(1).saturating_add(offset), None)
+    } else if let Some(offset) = input.strip_prefix('-') {
+        let offset: usize = offset.parse().map_err(|_| invalid())?;
+        let line = from.y.saturating_add(1).saturating_sub(offset);
+        (line, None)
+    } else {
+        let (line, column) = match input.split_once(':') {
+            Some((line, column)) => (line, Some(column)),
+            None => (input, None),
+        };
+        let line: usize = line.parse().map_err(|_| invalid())?;
+        let column: Option<usize> = match column {
+            Some(column) => Some(column.parse().map_err(|_| invalid())?),
+            None => None,
+        };
+        (line, column)
+    };
+    if line == 0 || line > len.max(1) {
+        return Err(format!(
+            "ERR: line {} is out of range (1-{})",
+            line,
+            len.max(1)
+        ));
+    }
+    let y = line - 1;
+    let width = document.row(y).map_or(0, Row::len);
+    let x = match column {
+        Some(column) if column == 0 || column > width + 1 => {
+            return Err(format!(
+                "ERR: column {} is out of range (1-{})",
+                column,
+                width + 1
+            ));
+        }
+        Some(column) => column - 1,
+        None => 0,
+    };
+    Ok(Position { x, y })
+}
+
 fn die(err: &std::io::Error) {
     Terminal::clear_screen();
     panic!("{}", err)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::default();
+        document.replace(&Position::default(), &Position::default(), text);
+        document
+    }
+
+    fn location(input: &str, from_y: usize, document: &Document) -> Result<(usize, usize), String> {
+        let from = Position { x: 0, y: from_y };
+        parse_location(input, &from, document).map(|at| (at.x, at.y))
+    }
+
+    #[test]
+    fn parses_lines_and_columns() {
+        let document = document("one\ntwo\nthree");
+        assert_eq!(location("2", 0, &document), Ok((0, 1)));
+        assert_eq!(location("3:6", 0, &document), Ok((5, 2)));
+        assert_eq!(
+            location("3:7", 0, &document),
+            Err("ERR: column 7 is out of range (1-6)".to_string())
+        );
+        assert_eq!(
+            location("4", 0, &document),
+            Err("ERR: line 4 is out of range (1-3)".to_string())
+        );
+        assert_eq!(
+            location("two", 0, &document),
+            Err("ERR: invalid location `two`".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_offsets_and_percentages() {
+        let document = document("1\n2\n3\n4\n5\n6\n7\n8\n9\n10");
+        assert_eq!(location("+3", 4, &document), Ok((0, 7)));
+        assert_eq!(
+            location("-10", 4, &document),
+            Err("ERR: line 0 is out of range (1-10)".to_string())
+        );
+        assert_eq!(location("-2", 4, &document), Ok((0, 2)));
+        assert_eq!(location("0%", 4, &document), Ok((0, 0)));
+        assert_eq!(location("25%", 4, &document), Ok((0, 2)));
+        assert_eq!(location("100%", 4, &document), Ok((0, 9)));
+        assert_eq!(
+            location("101%", 4, &document),
+            Err("ERR: 101% is out of range".to_string())
+        );
+    }
+}
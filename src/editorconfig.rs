@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = ".editorconfig";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum IndentStyle {
+    Space,
+    Tab,
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+            Self::Cr => "\r",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    Utf8Bom,
+    Latin1,
+    Utf16Be,
+    Utf16Le,
+}
+
+const BOM: char = '\u{feff}';
+
+impl Charset {
+    pub fn decode(self, bytes: Vec<u8>) -> Result<String, Error> {
+        match self {
+            Self::Utf8 | Self::Utf8Bom => {
+                let mut text = String::from_utf8(bytes)
+                    .map_err(|_| invalid_data("text is not valid for the charset"))?;
+                if text.starts_with(BOM) {
+                    text.drain(..BOM.len_utf8());
+                }
+                Ok(text)
+            }
+            Self::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+            Self::Utf16Be | Self::Utf16Le => {
+                let units = bytes.chunks(2).map(|pair| {
+                    let pair = [pair[0], pair.get(1).copied().unwrap_or(0)];
+                    if matches!(self, Self::Utf16Be) {
+                        u16::from_be_bytes(pair)
+                    } else {
+                        u16::from_le_bytes(pair)
+                    }
+                });
+                let mut text = char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .map_err(|_| invalid_data("text is not valid for the charset"))?;
+                if text.starts_with(BOM) {
+                    text.drain(..BOM.len_utf8());
+                }
+                Ok(text)
+            }
+        }
+    }
+
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Utf8 => Ok(text.as_bytes().to_vec()),
+            Self::Utf8Bom => Ok(format!("{}{}", BOM, text).into_bytes()),
+            Self::Latin1 => text
+                .chars()
+                .map(u8::try_from)
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid_data("text is not valid latin1")),
+            Self::Utf16Be => Ok(format!("{}{}", BOM, text)
+                .encode_utf16()
+                .flat_map(u16::to_be_bytes)
+                .collect()),
+            Self::Utf16Le => Ok(format!("{}{}", BOM, text)
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect()),
+        }
+    }
+}
+
+/// The `.editorconfig` properties chewol honors. Unset properties keep the
+/// editor's defaults.
+#[derive(Default)]
+pub struct Properties {
+    pub indent_style: Option<IndentStyle>,
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<LineEnding>,
+    pub charset: Option<Charset>,
+    pub trim_trailing_whitespace: bool,
+    pub insert_final_newline: Option<bool>,
+    pub max_line_length: Option<usize>,
+}
+
+/// Resolves the properties for the file at `path` from the `.editorconfig`
+/// files in its directory and those above it, up to one declaring
+/// `root = true`. Nearer files and later sections take precedence.
+pub fn properties(path: &Path) -> Properties {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut configs = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let Ok(contents) = fs::read_to_string(dir.join(CONFIG_FILE)) else {
+            continue;
+        };
+        let (root, sections) = parse(&contents);
+        configs.push((dir.to_path_buf(), sections));
+        if root {
+            break;
+        }
+    }
+
+    let mut values = HashMap::new();
+    for (dir, sections) in configs.iter().rev() {
+        let Some(relative) = relative_path(&path, dir) else {
+            continue;
+        };
+        let name = relative.rsplit('/').next().unwrap_or_default();
+        for (glob, pairs) in sections {
+            let is_match = match glob.strip_prefix('/') {
+                Some(glob) => matches(glob, &relative),
+                None if glob.contains('/') => matches(glob, &relative),
+                None => matches(glob, name),
+            };
+            if is_match {
+                values.extend(pairs.iter().cloned());
+            }
+        }
+    }
+    values.retain(|_, value| value != "unset");
+    properties_from(&values)
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+type Section = (String, Vec<(String, String)>);
+
+/// Parses an `.editorconfig` file into whether it is the root one and its
+/// sections, each a glob and its lowercased `key = value` pairs.
+fn parse(contents: &str) -> (bool, Vec<Section>) {
+    let mut root = false;
+    let mut sections: Vec<Section> = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(glob) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            sections.push((glob.to_string(), Vec::new()));
+        } else if let Some((key, value)) = line.split_once('=') {
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_lowercase();
+            match sections.last_mut() {
+                Some((_, pairs)) => pairs.push((key, value)),
+                None if key == "root" => root = value == "true",
+                None => (),
+            }
+        }
+    }
+    (root, sections)
+}
+
+fn properties_from(values: &HashMap<String, String>) -> Properties {
+    let get = |key: &str| values.get(key).map(String::as_str);
+    let indent_style = match get("indent_style") {
+        Some("space") => Some(IndentStyle::Space),
+        Some("tab") => Some(IndentStyle::Tab),
+        _ => None,
+    };
+    let mut tab_width = get("tab_width").and_then(|width| width.parse().ok());
+    let mut indent_size = match get("indent_size") {
+        Some("tab") => tab_width,
+        size => size.and_then(|size| size.parse().ok()),
+    };
+    if tab_width.is_none() {
+        tab_width = indent_size;
+    }
+    if indent_size.is_none() && indent_style == Some(IndentStyle::Tab) {
+        indent_size = tab_width;
+    }
+    Properties {
+        indent_style,
+        indent_size,
+        tab_width,
+        end_of_line: match get("end_of_line") {
+            Some("lf") => Some(LineEnding::Lf),
+            Some("crlf") => Some(LineEnding::CrLf),
+            Some("cr") => Some(LineEnding::Cr),
+            _ => None,
+        },
+        charset: match get("charset") {
+            Some("utf-8") => Some(Charset::Utf8),
+            Some("utf-8-bom") => Some(Charset::Utf8Bom),
+            Some("latin1") => Some(Charset::Latin1),
+            Some("utf-16be") => Some(Charset::Utf16Be),
+            Some("utf-16le") => Some(Charset::Utf16Le),
+            _ => None,
+        },
+        trim_trailing_whitespace: get("trim_trailing_whitespace") == Some("true"),
+        insert_final_newline: match get("insert_final_newline") {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            _ => None,
+        },
+        max_line_length: get("max_line_length").and_then(|length| length.parse().ok()),
+    }
+}
+
+/// The path of `path` below `dir`, with `/` separating its components.
+fn relative_path(path: &Path, dir: &PathBuf) -> Option<String> {
+    let relative = path.strip_prefix(dir).ok()?;
+    let components: Vec<_> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    Some(components.join("/"))
+}
+
+/// Matches `text` against an `.editorconfig` section glob, which supports `*`, `**`,
+/// `?`, `[chars]`, `[!chars]`, `{alt,ernatives}` and `{1..10}`.
+pub fn matches(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&glob, &text)
+}
+
+fn match_from(glob: &[char], text: &[char]) -> bool {
+    let Some((&first, rest)) = glob.split_first() else {
+        return text.is_empty();
+    };
+    match first {
+        '*' if rest.first() == Some(&'*') => {
+            (0..=text.len()).any(|skip| match_from(&rest[1..], &text[skip..]))
+        }
+        '*' => {
+            let limit = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=limit).any(|skip| match_from(rest, &text[skip..]))
+        }
+        '?' => text.first().is_some_and(|&c| c != '/') && match_from(rest, &text[1..]),
+        '[' => match rest.iter().position(|&c| c == ']') {
+            Some(close) => {
+                let (negated, class) = match rest[..close].split_first() {
+                    Some(('!', class)) => (true, class),
+                    _ => (false, &rest[..close]),
+                };
+                text.first().is_some_and(|&c| {
+                    c != '/'
+                        && in_class(class, c) != negated
+                        && match_from(&rest[close + 1..], &text[1..])
+                })
+            }
+            None => literal(first, rest, text),
+        },
+        '{' => match closing_brace(rest) {
+            Some(close) => match_braces(&rest[..close], &rest[close + 1..], text),
+            None => literal(first, rest, text),
+        },
+        '\\' => match rest.split_first() {
+            Some((&escaped, rest)) => literal(escaped, rest, text),
+            None => literal(first, rest, text),
+        },
+        _ => literal(first, rest, text),
+    }
+}
+
+fn literal(c: char, rest: &[char], text: &[char]) -> bool {
+    text.first() == Some(&c) && match_from(rest, &text[1..])
+}
+
+/// Whether `c` is in the characters and ranges like `a-z` of a bracket
+/// expression.
+pub fn in_class(class: &[char], c: char) -> bool {
+    let mut index = 0;
+    while index < class.len() {
+        if class.get(index + 1) == Some(&'-') && index + 2 < class.len() {
+            if (class[index]..=class[index + 2]).contains(&c) {
+                return true;
+            }
+            index += 3;
+        } else {
+            if class[index] == c {
+                return true;
+            }
+            index += 1;
+        }
+    }
+    false
+}
+
+/// The index in `glob` of the `}` closing a `{` just before it.
+fn closing_brace(glob: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    for (index, &c) in glob.iter().enumerate() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(index),
+            '}' => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Matches the braces holding `inner`, followed by `rest`, against `text`.
+fn match_braces(inner: &[char], rest: &[char], text: &[char]) -> bool {
+    let inner_text: String = inner.iter().collect();
+    if let Some((low, high)) = inner_text.split_once("..") {
+        if let (Ok(low), Ok(high)) = (low.parse::<i64>(), high.parse::<i64>()) {
+            return (1..=text.len()).any(|len| {
+                let number: String = text[..len].iter().collect();
+                number
+                    .parse::<i64>()
+                    .is_ok_and(|number| (low..=high).contains(&number))
+                    && match_from(rest, &text[len..])
+            });
+        }
+    }
+
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, &c) in inner.iter().enumerate() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(&inner[start..index]);
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    alternatives.push(&inner[start..]);
+    if alternatives.len() == 1 {
+        let mut glob = vec!['\\', '{'];
+        glob.extend_from_slice(inner);
+        glob.extend_from_slice(&['\\', '}']);
+        glob.extend_from_slice(rest);
+        return match_from(&glob, text);
+    }
+    alternatives.into_iter().any(|alternative| {
+        let mut glob = alternative.to_vec();
+        glob.extend_from_slice(rest);
+        match_from(&glob, text)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("**.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/a/b/main.rs"));
+        assert!(matches("?.c", "a.c"));
+        assert!(!matches("?.c", "ab.c"));
+        assert!(matches("[a-c].py", "b.py"));
+        assert!(!matches("[!a-c].py", "b.py"));
+        assert!(matches(r"\*.md", "*.md"));
+        assert!(!matches(r"\*.md", "a.md"));
+    }
+
+    #[test]
+    fn matches_braces() {
+        assert!(matches("*.{js,ts}", "app.ts"));
+        assert!(!matches("*.{js,ts}", "app.rs"));
+        assert!(matches("{a,{b,c}}.txt", "c.txt"));
+        assert!(matches("file{1..10}.txt", "file10.txt"));
+        assert!(!matches("file{1..10}.txt", "file11.txt"));
+        assert!(matches("{single}.txt", "{single}.txt"));
+    }
+
+    #[test]
+    fn parses_sections() {
+        let (root, sections) = parse(
+            "root = true\n; comment\n[*]\nIndent_Style = Space\n\n# comment\n[*.{c,h}]\ntab_width=8\n",
+        );
+        assert!(root);
+        assert_eq!(
+            sections,
+            [
+                (
+                    "*".to_string(),
+                    vec![("indent_style".to_string(), "space".to_string())]
+                ),
+                (
+                    "*.{c,h}".to_string(),
+                    vec![("tab_width".to_string(), "8".to_string())]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn derives_indent_sizes() {
+        let values = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+        let properties = properties_from(&values(&[("indent_size", "tab"), ("tab_width", "4")]));
+        assert_eq!(
+            (properties.indent_size, properties.tab_width),
+            (Some(4), Some(4))
+        );
+        let properties = properties_from(&values(&[("indent_size", "2")]));
+        assert_eq!(
+            (properties.indent_size, properties.tab_width),
+            (Some(2), Some(2))
+        );
+        let properties = properties_from(&values(&[("indent_style", "tab"), ("tab_width", "8")]));
+        assert_eq!(
+            (properties.indent_size, properties.tab_width),
+            (Some(8), Some(8))
+        );
+    }
+
+    #[test]
+    fn charsets_round_trip() {
+        for charset in [
+            Charset::Utf8,
+            Charset::Utf8Bom,
+            Charset::Utf16Be,
+            Charset::Utf16Le,
+        ] {
+            let bytes = charset.encode("héllo ✓").unwrap();
+            assert_eq!(charset.decode(bytes).unwrap(), "héllo ✓");
+        }
+        assert_eq!(Charset::Latin1.encode("é").unwrap(), [0xe9]);
+        assert!(Charset::Latin1.encode("✓").is_err());
+    }
+}
@@ -0,0 +1,106 @@
+/// Bonus for each character of the pattern found in the candidate.
+const MATCH_BONUS: usize = 1;
+/// Bonus for a character found right after the previous one.
+const CONSECUTIVE_BONUS: usize = 8;
+/// Bonus for a character that starts a word, a path component or a hump of
+/// camel case.
+const WORD_START_BONUS: usize = 6;
+/// Bonus for a character found in the last path component.
+const FILE_NAME_BONUS: usize = 2;
+
+/// Scores how well `pattern` matches `candidate` when its characters are
+/// found in order, ignoring case, or returns none if they are not. Higher is
+/// better; whitespace in the pattern is ignored.
+pub fn score(pattern: &str, candidate: &str) -> Option<usize> {
+    let pattern: Vec<char> = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(lowercase)
+        .collect();
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    let chars: Vec<char> = candidate.chars().collect();
+    let name_start = chars.iter().rposition(|&c| c == '/').map_or(0, |i| i + 1);
+    let bonus = |index: usize| {
+        let mut bonus = MATCH_BONUS;
+        let previous = index.checked_sub(1).map(|i| chars[i]);
+        if previous.is_none_or(|c| matches!(c, '/' | '_' | '-' | '.' | ' '))
+            || previous.is_some_and(char::is_lowercase) && chars[index].is_uppercase()
+        {
+            bonus += WORD_START_BONUS;
+        }
+        if index >= name_start {
+            bonus += FILE_NAME_BONUS;
+        }
+        bonus
+    };
+
+    // The best score for the pattern so far with its last character found
+    // at each index of the candidate.
+    let mut best: Vec<Option<usize>> = Vec::new();
+    for (number, &wanted) in pattern.iter().enumerate() {
+        let mut next = vec![None; chars.len()];
+        // The best score with the last character found before the previous
+        // index.
+        let mut before = if number == 0 { Some(0) } else { None };
+        for (index, &c) in chars.iter().enumerate() {
+            let previous = index
+                .checked_sub(1)
+                .and_then(|i| best.get(i).copied().flatten());
+            if lowercase(c) == wanted {
+                let consecutive = previous.map(|score| score + CONSECUTIVE_BONUS);
+                next[index] = consecutive.max(before).map(|score| score + bonus(index));
+            }
+            before = before.max(previous);
+        }
+        best = next;
+    }
+    best.into_iter().flatten().max()
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn needs_the_characters_in_order() {
+        assert!(score("mrs", "src/main.rs").is_some());
+        assert_eq!(score("rsm", "src/main.rs"), None);
+        assert_eq!(score("xyz", "src/main.rs"), None);
+        assert_eq!(score("", "anything"), Some(0));
+        assert_eq!(
+            score("MAIN rs", "src/main.rs"),
+            score("mainrs", "src/main.rs")
+        );
+    }
+
+    #[test]
+    fn prefers_consecutive_characters() {
+        assert!(score("main", "src/main.rs") > score("main", "src/mxaxixn.rs"));
+    }
+
+    #[test]
+    fn prefers_word_starts() {
+        assert!(score("fb", "foo_bar") > score("fb", "fooxbar"));
+        assert!(score("fb", "fooBar") > score("fb", "foobar"));
+    }
+
+    #[test]
+    fn prefers_the_file_name() {
+        assert!(score("edit", "src/editor.rs") > score("edit", "editor/src.rs"));
+    }
+
+    #[test]
+    fn finds_the_best_alignment() {
+        // The later `ab` is consecutive, which beats taking the first `a`.
+        assert_eq!(
+            score("ab", "a_xab"),
+            Some(2 * (MATCH_BONUS + FILE_NAME_BONUS) + CONSECUTIVE_BONUS)
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use crate::editorconfig;
+use crate::search::Query;
+use crate::Position;
+
+use std::fs::{self, DirEntry};
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+
+const IGNORE_FILE: &str = ".gitignore";
+/// How much of a file is checked for a NUL byte to tell whether it is binary.
+const BINARY_CHECK_LEN: usize = 8000;
+/// How many hits a search lists at most.
+const MAX_HITS: usize = 10_000;
+
+/// A line matching a project-wide search, and where its first match is.
+pub struct Hit {
+    pub path: String,
+    pub position: Position,
+    pub text: String,
+}
+
+/// A pattern from a `.gitignore` file.
+struct Rule {
+    /// The directory of the file it is from, relative to the search root.
+    dir: String,
+    glob: String,
+    negated: bool,
+    dir_only: bool,
+    /// Whether it matches paths relative to `dir` rather than file names.
+    anchored: bool,
+}
+
+/// Searches the lines of the files under `root` for `query`, skipping binary
+/// files. Paths are relative to `root` and sorted. Returns the first
+/// `MAX_HITS` hits, and whether there were more.
+pub fn search(root: &Path, query: &Query) -> (Vec<Hit>, bool) {
+    let mut hits = Vec::new();
+    for path in files(root) {
+        if hits.len() > MAX_HITS {
+            break;
+        }
+        search_file(&root.join(&path), &path, query, &mut hits);
+    }
+    let truncated = hits.len() > MAX_HITS;
+    hits.truncate(MAX_HITS);
+    (hits, truncated)
+}
+
+/// The paths of the files under `root`, relative to it and sorted, leaving
+/// out the `.git` directory and what `.gitignore` files ignore.
+pub fn files(root: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    walk(root, "", &mut Vec::new(), &mut files);
+    files
+}
+
+fn walk(dir: &Path, relative: &str, rules: &mut Vec<Rule>, files: &mut Vec<String>) {
+    let inherited = rules.len();
+    if let Ok(contents) = fs::read_to_string(dir.join(IGNORE_FILE)) {
+        rules.extend(parse(&contents, relative));
+    }
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .map(|entries| entries.flatten().collect())
+        .unwrap_or_default();
+    entries.sort_by_key(DirEntry::file_name);
+    for entry in entries {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = if relative.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", relative, name)
+        };
+        if name == ".git" || is_ignored(rules, &path, file_type.is_dir()) {
+            continue;
+        }
+        if file_type.is_dir() {
+            walk(&entry.path(), &path, rules, files);
+        } else if file_type.is_file() {
+            files.push(path);
+        }
+    }
+    rules.truncate(inherited);
+}
+
+fn search_file(file: &Path, path: &str, query: &Query, hits: &mut Vec<Hit>) {
+    let Ok(bytes) = fs::read(file) else {
+        return;
+    };
+    if bytes.iter().take(BINARY_CHECK_LEN).any(|&byte| byte == 0) {
+        return;
+    }
+    let Ok(contents) = String::from_utf8(bytes) else {
+        return;
+    };
+    for (y, line) in contents.lines().enumerate() {
+        if hits.len() > MAX_HITS {
+            return;
+        }
+        if let Some((start, _)) = query.find_at(line, 0) {
+            hits.push(Hit {
+                path: path.to_string(),
+                position: Position {
+                    x: line[..start].graphemes(true).count(),
+                    y,
+                },
+                text: line.to_string(),
+            });
+        }
+    }
+}
+
+/// Parses the patterns of a `.gitignore` file in the directory `dir`.
+fn parse(contents: &str, dir: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (negated, glob) = match line.strip_prefix('!') {
+            Some(glob) => (true, glob),
+            None => (false, line),
+        };
+        let (dir_only, glob) = match glob.strip_suffix('/') {
+            Some(glob) => (true, glob),
+            None => (false, glob),
+        };
+        rules.push(Rule {
+            dir: dir.to_string(),
+            glob: glob.strip_prefix('/').unwrap_or(glob).to_string(),
+            negated,
+            dir_only,
+            anchored: glob.contains('/'),
+        });
+    }
+    rules
+}
+
+/// Whether the last of `rules` that matches `path` ignores it.
+fn is_ignored(rules: &[Rule], path: &str, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for rule in rules {
+        if rule.dir_only && !is_dir {
+            continue;
+        }
+        let relative = if rule.dir.is_empty() {
+            path
+        } else {
+            let Some(relative) = path
+                .strip_prefix(rule.dir.as_str())
+                .and_then(|path| path.strip_prefix('/'))
+            else {
+                continue;
+            };
+            relative
+        };
+        let is_match = if rule.anchored {
+            matches(&rule.glob, relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or_default();
+            matches(&rule.glob, name)
+        };
+        if is_match {
+            ignored = !rule.negated;
+        }
+    }
+    ignored
+}
+
+/// Matches `text` against a `.gitignore` glob. `*`, `?` and `[chars]` or
+/// `[!chars]` don't match a `/`, while a `**` between slashes, or at either
+/// end, matches any number of directories.
+fn matches(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&glob, 0, &text)
+}
+
+/// Matches `text` against `glob` from its character `at` on.
+fn match_from(glob: &[char], at: usize, text: &[char]) -> bool {
+    let Some(&first) = glob.get(at) else {
+        return text.is_empty();
+    };
+    let starts_component = at == 0 || glob[at - 1] == '/';
+    match first {
+        '*' if glob.get(at + 1) == Some(&'*')
+            && starts_component
+            && matches!(glob.get(at + 2), None | Some('/')) =>
+        {
+            if at + 2 == glob.len() {
+                // A trailing `/**` matches everything inside the directory.
+                !text.is_empty()
+            } else {
+                (0..=text.len())
+                    .filter(|&skip| skip == 0 || text[skip - 1] == '/')
+                    .any(|skip| match_from(glob, at + 3, &text[skip..]))
+            }
+        }
+        '*' => {
+            let limit = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=limit).any(|skip| match_from(glob, at + 1, &text[skip..]))
+        }
+        '?' => text.first().is_some_and(|&c| c != '/') && match_from(glob, at + 1, &text[1..]),
+        '[' => {
+            let start = at + 1;
+            let negated = matches!(glob.get(start), Some('!' | '^'));
+            let class_start = if negated { start + 1 } else { start };
+            // A `]` right after the opening bracket is part of the class.
+            let close = glob
+                .iter()
+                .skip(class_start + 1)
+                .position(|&c| c == ']')
+                .map(|index| index + class_start + 1);
+            match close {
+                Some(close) => text.first().is_some_and(|&c| {
+                    c != '/'
+                        && editorconfig::in_class(&glob[class_start..close], c) != negated
+                        && match_from(glob, close + 1, &text[1..])
+                }),
+                None => literal(first, glob, at + 1, text),
+            }
+        }
+        '\\' if at + 1 < glob.len() => literal(glob[at + 1], glob, at + 2, text),
+        _ => literal(first, glob, at + 1, text),
+    }
+}
+
+/// Whether `text` starts with `c` and the rest of it matches `glob` from its
+/// character `at` on.
+fn literal(c: char, glob: &[char], at: usize, text: &[char]) -> bool {
+    text.first() == Some(&c) && match_from(glob, at, &text[1..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(contents: &str, path: &str, is_dir: bool) -> bool {
+        is_ignored(&parse(contents, ""), path, is_dir)
+    }
+
+    #[test]
+    fn matches_names_at_any_depth() {
+        assert!(ignored("*.log", "debug.log", false));
+        assert!(ignored("*.log", "a/b/debug.log", false));
+        assert!(!ignored("*.log", "debug.logs", false));
+        assert!(ignored("cache?", "src/cache1", true));
+        assert!(ignored("[Tt]emp", "a/temp", false));
+        assert!(ignored("[!a-y]", "z", false));
+        assert!(!ignored("[!a-y]", "b", false));
+    }
+
+    #[test]
+    fn anchors_patterns_with_slashes() {
+        assert!(ignored("/target", "target", true));
+        assert!(!ignored("/target", "sub/target", true));
+        assert!(ignored("doc/*.txt", "doc/notes.txt", false));
+        assert!(!ignored("doc/*.txt", "doc/server/notes.txt", false));
+    }
+
+    #[test]
+    fn matches_double_asterisks_as_directories() {
+        assert!(ignored("**/build", "build", true));
+        assert!(ignored("**/build", "a/b/build", true));
+        assert!(ignored("a/**/b", "a/b", false));
+        assert!(ignored("a/**/b", "a/x/y/b", false));
+        assert!(!ignored("a/**/b", "ab", false));
+        assert!(ignored("logs/**", "logs/today/out", false));
+        assert!(!ignored("logs/**", "logs", true));
+        assert!(ignored("a**z", "abcz", false));
+        assert!(!ignored("a**z", "a/z", false));
+    }
+
+    #[test]
+    fn takes_braces_and_escapes_literally() {
+        assert!(ignored("{a,b}.txt", "{a,b}.txt", false));
+        assert!(!ignored("{a,b}.txt", "a.txt", false));
+        assert!(ignored(r"\#notes", "#notes", false));
+        assert!(ignored(r"\!important", "!important", false));
+    }
+
+    #[test]
+    fn applies_the_last_matching_rule() {
+        let contents = "*.log\n!keep.log\n# comment\nbuild/\n";
+        assert!(ignored(contents, "a.log", false));
+        assert!(!ignored(contents, "keep.log", false));
+        assert!(ignored(contents, "build", true));
+        assert!(!ignored(contents, "build", false));
+    }
+
+    #[test]
+    fn applies_rules_below_their_directory() {
+        let rules = parse("/out\n", "sub");
+        assert!(is_ignored(&rules, "sub/out", true));
+        assert!(!is_ignored(&rules, "out", true));
+        assert!(!is_ignored(&rules, "subway/out", true));
+    }
+}
@@ -1,29 +1,57 @@
 use termion::color;
 
+pub const SELECTION_BG_COLOR: color::Rgb = color::Rgb(7, 54, 66);
+
 #[derive(PartialEq, Clone, Copy)]
 pub enum Mode {
+    Anchor,
     Character,
+    Code,
     Comment,
+    Decorator,
+    Emphasis,
+    Heading,
+    Key,
+    Link,
+    ListMarker,
     Match,
     MultilineComment,
     None,
     PrimaryKeyword,
     Number,
+    Preprocessor,
+    Quote,
+    Section,
     SecondaryKeyword,
     String,
+    Strong,
 }
 
 impl Mode {
     pub fn to_color(self) -> impl color::Color {
         match self {
-            Mode::Character => color::Rgb(108, 113, 196),
-            Mode::Comment | Mode::MultilineComment => color::Rgb(133, 153, 0),
+            Mode::Anchor | Mode::Character | Mode::Emphasis => color::Rgb(108, 113, 196),
+            Mode::Comment | Mode::MultilineComment | Mode::Quote => color::Rgb(133, 153, 0),
+            Mode::Decorator | Mode::Heading | Mode::Section => color::Rgb(203, 75, 22),
+            Mode::Key | Mode::Link => color::Rgb(108, 153, 230),
             Mode::Match => color::Rgb(38, 139, 210),
-            Mode::PrimaryKeyword => color::Rgb(181, 137, 0),
+            Mode::PrimaryKeyword | Mode::ListMarker => color::Rgb(181, 137, 0),
             Mode::Number => color::Rgb(220, 163, 163),
-            Mode::SecondaryKeyword => color::Rgb(42, 161, 152),
+            Mode::Preprocessor | Mode::Strong => color::Rgb(220, 50, 47),
+            Mode::SecondaryKeyword | Mode::Code => color::Rgb(42, 161, 152),
             Mode::String => color::Rgb(211, 54, 130),
             Mode::None => color::Rgb(255, 255, 255),
         }
     }
 }
+
+/// A block comment, string or code block still open at the end of a row, by
+/// its index in the file type's block comments, strings or code blocks, or a
+/// block scalar by the indentation of the line that starts it.
+#[derive(PartialEq, Clone, Copy)]
+pub enum Open {
+    Comment(usize),
+    String(usize),
+    CodeBlock(usize),
+    BlockScalar(usize),
+}
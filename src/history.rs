@@ -0,0 +1,129 @@
+use crate::paths;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+
+const HISTORY_FILE: &str = "history";
+/// How many entries are kept for each kind of prompt.
+const HISTORY_LIMIT: usize = 100;
+
+pub const SEARCH: &str = "search";
+pub const REPLACE: &str = "replace";
+pub const GOTO: &str = "goto";
+pub const GREP: &str = "grep";
+pub const SHELL: &str = "shell";
+
+/// What was entered in each kind of prompt, oldest first and without
+/// duplicates, persisted in the data directory one entry per line as
+/// `kind = text`, with newlines and backslashes in the text escaped.
+#[derive(Default)]
+pub struct History {
+    entries: BTreeMap<String, Vec<String>>,
+    /// Whether there are entries the history file doesn't have yet.
+    is_dirty: bool,
+}
+
+impl History {
+    pub fn load() -> Self {
+        let mut history = Self::default();
+        let contents = file_path().and_then(|path| fs::read_to_string(path).ok());
+        for line in contents.unwrap_or_default().lines() {
+            if let Some((kind, text)) = line.split_once(" = ") {
+                history.add(kind, &unescape(text));
+            }
+        }
+        history.is_dirty = false;
+        history
+    }
+
+    pub fn entries(&self, kind: &str) -> &[String] {
+        self.entries.get(kind).map_or(&[], Vec::as_slice)
+    }
+
+    /// Adds `text` as the newest entry of `kind`.
+    pub fn add(&mut self, kind: &str, text: &str) {
+        let entries = self.entries.entry(kind.to_string()).or_default();
+        entries.retain(|entry| entry != text);
+        entries.push(text.to_string());
+        if entries.len() > HISTORY_LIMIT {
+            entries.remove(0);
+        }
+        self.is_dirty = true;
+    }
+
+    /// Rewrites the history file if entries were added since it was last
+    /// written. A failed write is not retried until the next entry.
+    pub fn save(&mut self) -> Result<(), Error> {
+        if !self.is_dirty {
+            return Ok(());
+        }
+        self.is_dirty = false;
+        let path = file_path().ok_or_else(|| Error::other("no data directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut contents = String::new();
+        for (kind, entries) in &self.entries {
+            for text in entries {
+                let _ = writeln!(contents, "{} = {}", kind, escape(text));
+            }
+        }
+        fs::write(path, contents)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.next_if(|next| c == '\\' && matches!(next, 'n' | '\\')) {
+            Some('n') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn file_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(HISTORY_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_newlines_and_backslashes() {
+        let text = "a\\nb\nc\\";
+        assert_eq!(escape(text), "a\\\\nb\\nc\\\\");
+        assert_eq!(unescape(&escape(text)), text);
+    }
+
+    #[test]
+    fn keeps_other_backslashes() {
+        assert_eq!(unescape(r"\d+\t\"), r"\d+\t\");
+    }
+
+    #[test]
+    fn keeps_entries_unique_and_limited() {
+        let mut history = History::default();
+        for index in 0..=HISTORY_LIMIT {
+            history.add(SEARCH, &index.to_string());
+        }
+        history.add(SEARCH, "5");
+        let entries = history.entries(SEARCH);
+        assert_eq!(entries.len(), HISTORY_LIMIT);
+        assert_eq!(entries.first().map(String::as_str), Some("1"));
+        assert_eq!(entries.last().map(String::as_str), Some("5"));
+        assert_eq!(entries.iter().filter(|entry| *entry == "5").count(), 1);
+        assert!(history.entries(GOTO).is_empty());
+    }
+}
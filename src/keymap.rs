@@ -0,0 +1,201 @@
+use crate::keys;
+use crate::paths;
+
+use std::collections::HashMap;
+use std::fs;
+use termion::event::Key;
+
+const KEYMAP_FILE: &str = "keymap";
+
+/// Bindings that reproduce chewol's built-in behavior. The keymap file is
+/// applied on top of these.
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("up", "cursor-up"),
+    ("down", "cursor-down"),
+    ("left", "cursor-left"),
+    ("right", "cursor-right"),
+    ("pageup", "page-up"),
+    ("ctrl-u", "page-up"),
+    ("pagedown", "page-down"),
+    ("ctrl-d", "page-down"),
+    ("home", "line-start"),
+    ("ctrl-a", "line-start"),
+    ("end", "line-end"),
+    ("ctrl-e", "line-end"),
+    ("backspace", "delete-backward"),
+    ("delete", "delete-forward"),
+    ("ctrl-z", "undo"),
+    ("ctrl-y", "redo"),
+    ("ctrl-f", "search"),
+    ("alt-r", "replace"),
+    ("ctrl-l", "clear-highlights"),
+    ("alt-g", "grep"),
+    ("ctrl-o", "open-file"),
+    ("ctrl-g", "goto"),
+    ("alt-m", "set-mark"),
+    ("alt-'", "goto-mark"),
+    ("alt-%", "match-bracket"),
+    ("alt-,", "jump-back"),
+    ("alt-.", "jump-forward"),
+    ("ctrl-q", "quit"),
+    ("ctrl-s", "save"),
+    ("alt-x", "execute-command"),
+    ("ctrl-n", "complete"),
+    ("tab", "snippet-next"),
+    ("backtab", "snippet-previous"),
+    ("alt-n", "next-buffer"),
+    ("alt-p", "previous-buffer"),
+    ("alt-|", "filter"),
+    ("alt-q", "reflow"),
+    ("alt-v", "toggle-modal-editing"),
+    ("f3", "toggle-macro-recording"),
+    ("f4", "replay-macro"),
+    ("alt-@", "replay-macro-repeatedly"),
+    ("alt-w", "save-macro"),
+    ("alt-l", "load-macro"),
+];
+
+pub enum Binding<'a> {
+    Command(&'a str),
+    /// The keys so far begin one or more longer bindings.
+    Prefix,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, String>,
+}
+
+impl Keymap {
+    /// Loads the default bindings overlaid with those in the keymap file.
+    /// Each line of the file is `keys = command`, where `keys` is one or more
+    /// space-separated keys such as `ctrl-x ctrl-s`, and the command `none`
+    /// removes a binding. Lines naming an unknown key or a command for which
+    /// `is_command` is false are skipped and reported in the returned errors.
+    pub fn load(is_command: impl Fn(&str) -> bool) -> (Self, Vec<String>) {
+        let contents = paths::config_dir()
+            .and_then(|dir| fs::read_to_string(dir.join(KEYMAP_FILE)).ok())
+            .unwrap_or_default();
+        Self::parse(&contents, is_command)
+    }
+
+    fn parse(contents: &str, is_command: impl Fn(&str) -> bool) -> (Self, Vec<String>) {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        for (chord, command) in DEFAULT_BINDINGS {
+            if let Some(chord) = keys::parse_sequence(chord) {
+                keymap.bindings.insert(chord, (*command).to_string());
+            }
+        }
+        let mut errors = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(error) = keymap.bind_line(line, &is_command) {
+                errors.push(format!("keymap line {}: {}", index + 1, error));
+            }
+        }
+        (keymap, errors)
+    }
+
+    fn bind_line(&mut self, line: &str, is_command: impl Fn(&str) -> bool) -> Result<(), String> {
+        let (chord, command) = line
+            .rsplit_once('=')
+            .ok_or_else(|| "expected `keys = command`".to_string())?;
+        let (chord, command) = (chord.trim(), command.trim());
+        let keys = keys::parse_sequence(chord)
+            .filter(|keys| !keys.is_empty())
+            .ok_or_else(|| format!("invalid keys `{}`", chord))?;
+        if command == "none" {
+            self.bindings.remove(&keys);
+        } else if is_command(command) {
+            self.bindings.insert(keys, command.to_string());
+        } else {
+            return Err(format!("unknown command `{}`", command));
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Binding<'_> {
+        if let Some(command) = self.bindings.get(keys) {
+            return Binding::Command(command);
+        }
+        if self
+            .bindings
+            .keys()
+            .any(|chord| chord.len() > keys.len() && chord.starts_with(keys))
+        {
+            return Binding::Prefix;
+        }
+        Binding::Unbound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> (Keymap, Vec<String>) {
+        Keymap::parse(contents, |command| {
+            DEFAULT_BINDINGS.iter().any(|(_, known)| *known == command)
+        })
+    }
+
+    fn command(keymap: &Keymap, chord: &str) -> Option<String> {
+        match keymap.lookup(&keys::parse_sequence(chord).unwrap()) {
+            Binding::Command(command) => Some(command.to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn binds_keys_from_the_file() {
+        let (keymap, errors) = parse("# comment\n\nalt-s = save\nctrl-x ctrl-c=quit\n");
+        assert!(errors.is_empty());
+        assert_eq!(command(&keymap, "alt-s").as_deref(), Some("save"));
+        assert_eq!(command(&keymap, "ctrl-x ctrl-c").as_deref(), Some("quit"));
+        assert_eq!(command(&keymap, "ctrl-s").as_deref(), Some("save"));
+    }
+
+    #[test]
+    fn looks_up_chords_by_prefix() {
+        let (keymap, _) = parse("ctrl-x ctrl-s = save");
+        let ctrl_x = keys::parse_sequence("ctrl-x").unwrap();
+        assert!(matches!(keymap.lookup(&ctrl_x), Binding::Prefix));
+        assert!(matches!(
+            keymap.lookup(&keys::parse_sequence("ctrl-x ctrl-s").unwrap()),
+            Binding::Command("save")
+        ));
+        assert!(matches!(
+            keymap.lookup(&keys::parse_sequence("ctrl-x ctrl-t").unwrap()),
+            Binding::Unbound
+        ));
+    }
+
+    #[test]
+    fn unbinds_defaults_with_none() {
+        let (keymap, errors) = parse("ctrl-q = none");
+        assert!(errors.is_empty());
+        assert_eq!(command(&keymap, "ctrl-q"), None);
+        assert_eq!(command(&keymap, "ctrl-s").as_deref(), Some("save"));
+    }
+
+    #[test]
+    fn reports_bad_lines_by_number() {
+        let (keymap, errors) =
+            parse("alt-s = save\nalt-t = frobnicate\n\nbogus-key = quit\nalt-u\nalt-v = quit");
+        assert_eq!(
+            errors,
+            [
+                "keymap line 2: unknown command `frobnicate`",
+                "keymap line 4: invalid keys `bogus-key`",
+                "keymap line 5: expected `keys = command`",
+            ]
+        );
+        assert_eq!(command(&keymap, "alt-t"), None);
+        assert_eq!(command(&keymap, "alt-v").as_deref(), Some("quit"));
+    }
+}
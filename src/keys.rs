@@ -0,0 +1,130 @@
+use termion::event::Key;
+
+/// Formats a key in the notation used by chewol's files, e.g. `ctrl-s`,
+/// `alt-x`, `enter` or `a`. Keys are separated by spaces in those files, so
+/// a space is written as `space`.
+pub fn to_string(key: Key) -> Option<String> {
+    let name = match key {
+        Key::Backspace => "backspace".to_string(),
+        Key::Left => "left".to_string(),
+        Key::Right => "right".to_string(),
+        Key::Up => "up".to_string(),
+        Key::Down => "down".to_string(),
+        Key::Home => "home".to_string(),
+        Key::End => "end".to_string(),
+        Key::PageUp => "pageup".to_string(),
+        Key::PageDown => "pagedown".to_string(),
+        Key::BackTab => "backtab".to_string(),
+        Key::Delete => "delete".to_string(),
+        Key::Insert => "insert".to_string(),
+        Key::Null => "null".to_string(),
+        Key::Esc => "esc".to_string(),
+        Key::F(n) => format!("f{}", n),
+        Key::Char(c) => char_name(c),
+        Key::Alt(c) => format!("alt-{}", char_name(c)),
+        Key::Ctrl(c) => format!("ctrl-{}", char_name(c)),
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Parses a single key written by `to_string`.
+pub fn parse(name: &str) -> Option<Key> {
+    if let Some(rest) = name.strip_prefix("ctrl-") {
+        return parse_char(rest).map(Key::Ctrl);
+    }
+    if let Some(rest) = name.strip_prefix("alt-") {
+        return parse_char(rest).map(Key::Alt);
+    }
+    let key = match name {
+        "backspace" => Key::Backspace,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backtab" => Key::BackTab,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "null" => Key::Null,
+        "esc" => Key::Esc,
+        _ => {
+            if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                return Some(Key::F(n));
+            }
+            return parse_char(name).map(Key::Char);
+        }
+    };
+    Some(key)
+}
+
+/// Parses a space-separated sequence of keys, failing if any key is invalid.
+pub fn parse_sequence(text: &str) -> Option<Vec<Key>> {
+    text.split_whitespace().map(parse).collect()
+}
+
+pub fn sequence_to_string(keys: &[Key]) -> String {
+    keys.iter()
+        .filter_map(|key| to_string(*key))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn char_name(c: char) -> String {
+    match c {
+        '\n' => "enter".to_string(),
+        '\t' => "tab".to_string(),
+        ' ' => "space".to_string(),
+        _ => c.to_string(),
+    }
+}
+
+fn parse_char(name: &str) -> Option<char> {
+    match name {
+        "enter" => Some('\n'),
+        "tab" => Some('\t'),
+        "space" => Some(' '),
+        _ => {
+            let mut chars = name.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            Some(c)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_named_and_modified_keys() {
+        assert_eq!(parse("ctrl-s"), Some(Key::Ctrl('s')));
+        assert_eq!(parse("alt-enter"), Some(Key::Alt('\n')));
+        assert_eq!(parse("space"), Some(Key::Char(' ')));
+        assert_eq!(parse("pagedown"), Some(Key::PageDown));
+        assert_eq!(parse("f12"), Some(Key::F(12)));
+        assert_eq!(parse("f"), Some(Key::Char('f')));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(parse("ctrl-ab"), None);
+        assert_eq!(parse("shift-a"), None);
+        assert_eq!(parse(""), None);
+        assert_eq!(parse_sequence("ctrl-x bogus"), None);
+    }
+
+    #[test]
+    fn sequences_round_trip() {
+        let keys = vec![Key::Ctrl('x'), Key::Char(' '), Key::Alt('\t'), Key::F(3)];
+        let text = sequence_to_string(&keys);
+        assert_eq!(text, "ctrl-x space alt-tab f3");
+        assert_eq!(parse_sequence(&text), Some(keys));
+    }
+}
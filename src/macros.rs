@@ -0,0 +1,91 @@
+use crate::keys;
+use crate::paths;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+use termion::event::Key;
+
+const MACROS_FILE: &str = "macros";
+
+/// Keyboard macros: the one being recorded, the one that replays by default,
+/// and named macros persisted in the data directory, one per line as
+/// `name = key key key`.
+#[derive(Default)]
+pub struct Macros {
+    current: Vec<Key>,
+    named: BTreeMap<String, Vec<Key>>,
+    recording: Option<Vec<Key>>,
+}
+
+impl Macros {
+    pub fn current(&self) -> &[Key] {
+        &self.current
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn load() -> Self {
+        let mut macros = Self::default();
+        let contents = file_path().and_then(|path| fs::read_to_string(path).ok());
+        for line in contents.unwrap_or_default().lines() {
+            if let Some((name, sequence)) = line.split_once('=') {
+                if let Some(keys) = keys::parse_sequence(sequence) {
+                    macros.named.insert(name.trim().to_string(), keys);
+                }
+            }
+        }
+        macros
+    }
+
+    pub fn record(&mut self, key: Key) {
+        if let Some(keys) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    /// Stores `current` under `name` and rewrites the macros file.
+    pub fn save(&mut self, name: &str) -> Result<(), Error> {
+        self.named.insert(name.to_string(), self.current.clone());
+        let path = file_path().ok_or_else(|| Error::other("no data directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut contents = String::new();
+        for (name, keys) in &self.named {
+            let _ = writeln!(contents, "{} = {}", name, keys::sequence_to_string(keys));
+        }
+        fs::write(path, contents)
+    }
+
+    /// Makes the macro saved as `name` the current one.
+    pub fn select(&mut self, name: &str) -> bool {
+        if let Some(keys) = self.named.get(name) {
+            self.current = keys.clone();
+            return true;
+        }
+        false
+    }
+
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    /// Finishes recording, dropping the trailing `len` keys that invoked the
+    /// stop, and returns how many keys were kept.
+    pub fn stop_recording(&mut self, len: usize) -> usize {
+        if let Some(mut keys) = self.recording.take() {
+            keys.truncate(keys.len().saturating_sub(len));
+            self.current = keys;
+        }
+        self.current.len()
+    }
+}
+
+fn file_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(MACROS_FILE))
+}
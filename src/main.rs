@@ -4,19 +4,41 @@
     clippy::missing_errors_doc,
     clippy::missing_panics_doc,
     clippy::return_self_not_must_use,
-    clippy::struct_excessive_bools
+    clippy::should_implement_trait,
+    clippy::struct_excessive_bools,
+    clippy::uninlined_format_args
 )]
 
+mod completion;
 mod document;
 mod editor;
+mod editorconfig;
 mod filetype;
+mod fuzzy;
+mod grep;
 mod highlighting;
+mod history;
+mod keymap;
+mod keys;
+mod macros;
+mod marks;
+mod paths;
+mod reflow;
+mod replace;
 mod row;
+mod search;
+mod shell;
+mod snippets;
+mod syntax;
 mod terminal;
+mod undo;
+mod vim;
 
 pub use document::Document;
 pub use editor::{Editor, Position, SearchDirection};
 pub use filetype::{FileType, HighlightOptions};
+pub use macros::Macros;
+pub use marks::Marks;
 pub use row::Row;
 pub use terminal::Terminal;
 
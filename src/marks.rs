@@ -0,0 +1,194 @@
+use crate::Position;
+
+use std::collections::BTreeMap;
+
+const JUMP_LIST_LENGTH: usize = 100;
+
+/// Named marks, the jump list and the ranges of an active snippet in a
+/// document. All hold positions that `adjust` keeps attached to their text as
+/// the document is edited.
+#[derive(Default)]
+pub struct Marks {
+    named: BTreeMap<String, Position>,
+    jumps: Vec<Position>,
+    /// Index into `jumps` while walking it; equal to its length otherwise.
+    current: usize,
+    /// Ranges that grow when text is inserted at either of their ends.
+    ranges: Vec<(Position, Position)>,
+}
+
+impl Marks {
+    /// Moves positions after an edit that replaced the text from `start` to
+    /// `end` with text ending at `new_end`. Positions inside the replaced text
+    /// collapse to `start`, except range ends, which move to `new_end`.
+    pub fn adjust(&mut self, start: &Position, end: &Position, new_end: &Position) {
+        for position in self.named.values_mut().chain(self.jumps.iter_mut()) {
+            if (position.y, position.x) >= (start.y, start.x) {
+                shift(position, start, end, new_end);
+            }
+        }
+        for (range_start, range_end) in &mut self.ranges {
+            if (range_start.y, range_start.x) > (start.y, start.x) {
+                shift(range_start, start, end, new_end);
+            }
+            if (range_end.y, range_end.x) < (end.y, end.x)
+                && (range_end.y, range_end.x) >= (start.y, start.x)
+            {
+                *range_end = new_end.clone();
+            } else if (range_end.y, range_end.x) >= (start.y, start.x) {
+                shift(range_end, start, end, new_end);
+            }
+        }
+    }
+
+    pub fn clear_ranges(&mut self) {
+        self.ranges.clear();
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Position> {
+        self.named.get(name)
+    }
+
+    /// Steps back through the jump list. Leaving the end of the list records
+    /// `from` so that `jump_forward` can return to it, in place of a newest
+    /// entry on the same line, so the first step back leaves that line.
+    pub fn jump_back(&mut self, from: &Position) -> Option<Position> {
+        if self.current == self.jumps.len() {
+            if self.jumps.last().is_some_and(|last| last.y == from.y) {
+                self.jumps.pop();
+            }
+            self.jumps.push(from.clone());
+            if self.jumps.len() > JUMP_LIST_LENGTH {
+                self.jumps.remove(0);
+            }
+            self.current = self.jumps.len() - 1;
+        }
+        self.current = self.current.checked_sub(1)?;
+        self.jumps.get(self.current).cloned()
+    }
+
+    pub fn jump_forward(&mut self) -> Option<Position> {
+        if self.current.saturating_add(1) >= self.jumps.len() {
+            return None;
+        }
+        self.current += 1;
+        self.jumps.get(self.current).cloned()
+    }
+
+    /// Records `from` as the position before a jump, discarding any entries
+    /// ahead of the current one.
+    pub fn push_jump(&mut self, from: Position) {
+        self.jumps.truncate(self.current);
+        if self.jumps.last().map(|last| last.y) != Some(from.y) {
+            self.jumps.push(from);
+        }
+        if self.jumps.len() > JUMP_LIST_LENGTH {
+            self.jumps.remove(0);
+        }
+        self.current = self.jumps.len();
+    }
+
+    pub fn range(&self, index: usize) -> Option<&(Position, Position)> {
+        self.ranges.get(index)
+    }
+
+    pub fn set(&mut self, name: &str, at: Position) {
+        self.named.insert(name.to_string(), at);
+    }
+
+    pub fn set_ranges(&mut self, ranges: Vec<(Position, Position)>) {
+        self.ranges = ranges;
+    }
+}
+
+/// Moves `position`, which is at or after `start`, past an edit that replaced
+/// the text from `start` to `end` with text ending at `new_end`.
+fn shift(position: &mut Position, start: &Position, end: &Position, new_end: &Position) {
+    if (position.y, position.x) < (end.y, end.x) {
+        *position = start.clone();
+    } else if position.y == end.y {
+        position.x = position.x - end.x + new_end.x;
+        position.y = new_end.y;
+    } else {
+        position.y = position.y - end.y + new_end.y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+
+    fn mark(marks: &Marks, name: &str) -> Option<(usize, usize)> {
+        marks.get(name).map(|position| (position.x, position.y))
+    }
+
+    fn lines(jump: Option<Position>) -> Option<usize> {
+        jump.map(|position| position.y)
+    }
+
+    #[test]
+    fn moves_marks_with_inserted_text() {
+        let mut marks = Marks::default();
+        marks.set("before", at(1, 0));
+        marks.set("same line", at(5, 1));
+        marks.set("below", at(2, 3));
+        // "ab\n" typed at column 2 of the second line.
+        marks.adjust(&at(2, 1), &at(2, 1), &at(0, 2));
+        assert_eq!(mark(&marks, "before"), Some((1, 0)));
+        assert_eq!(mark(&marks, "same line"), Some((3, 2)));
+        assert_eq!(mark(&marks, "below"), Some((2, 4)));
+        // "xy" typed at the start of the third line.
+        marks.adjust(&at(0, 2), &at(0, 2), &at(2, 2));
+        assert_eq!(mark(&marks, "same line"), Some((5, 2)));
+    }
+
+    #[test]
+    fn moves_marks_with_deleted_lines() {
+        let mut marks = Marks::default();
+        marks.set("before", at(1, 0));
+        marks.set("deleted", at(2, 1));
+        marks.set("after", at(3, 3));
+        // The second and third lines removed entirely.
+        marks.adjust(&at(0, 1), &at(0, 3), &at(0, 1));
+        assert_eq!(mark(&marks, "before"), Some((1, 0)));
+        assert_eq!(mark(&marks, "deleted"), Some((0, 1)));
+        assert_eq!(mark(&marks, "after"), Some((3, 1)));
+    }
+
+    #[test]
+    fn walks_the_jump_list() {
+        let mut marks = Marks::default();
+        assert_eq!(lines(marks.jump_back(&at(0, 0))), None);
+        let mut marks = Marks::default();
+        for y in [1, 5, 9] {
+            marks.push_jump(at(0, y));
+        }
+        assert_eq!(lines(marks.jump_back(&at(0, 12))), Some(9));
+        assert_eq!(lines(marks.jump_back(&at(0, 9))), Some(5));
+        assert_eq!(lines(marks.jump_forward()), Some(9));
+        assert_eq!(lines(marks.jump_forward()), Some(12));
+        assert_eq!(lines(marks.jump_forward()), None);
+        assert_eq!(lines(marks.jump_back(&at(0, 12))), Some(9));
+        assert_eq!(lines(marks.jump_back(&at(0, 9))), Some(5));
+        // A new jump drops the entries ahead of the current one.
+        marks.push_jump(at(0, 5));
+        assert_eq!(lines(marks.jump_back(&at(0, 30))), Some(5));
+        assert_eq!(lines(marks.jump_back(&at(0, 5))), Some(1));
+        assert_eq!(lines(marks.jump_back(&at(0, 1))), None);
+        assert_eq!(lines(marks.jump_forward()), Some(5));
+        assert_eq!(lines(marks.jump_forward()), Some(30));
+        assert_eq!(lines(marks.jump_forward()), None);
+    }
+
+    #[test]
+    fn moves_jumps_with_edits() {
+        let mut marks = Marks::default();
+        marks.push_jump(at(0, 4));
+        marks.adjust(&at(0, 0), &at(0, 2), &at(0, 0));
+        assert_eq!(lines(marks.jump_back(&at(0, 9))), Some(2));
+    }
+}
@@ -0,0 +1,23 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Directory for user configuration (`$XDG_CONFIG_HOME/chewol`, or
+/// `~/.config/chewol`).
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Directory for files chewol writes on the user's behalf, following the XDG
+/// base directory convention (`$XDG_DATA_HOME/chewol`, or
+/// `~/.local/share/chewol`).
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn xdg_dir(variable: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(variable) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(fallback),
+    };
+    Some(base.join("chewol"))
+}
@@ -0,0 +1,138 @@
+use crate::row::display_width;
+use crate::Document;
+
+/// Splits `line` into its marker, the indentation and any of the `comments`
+/// tokens that follow it; its prefix, the marker and the whitespace after it;
+/// and the text after the prefix.
+fn split_prefix<'a>(line: &'a str, comments: &[String]) -> (&'a str, &'a str, &'a str) {
+    let indent = line.len() - line.trim_start().len();
+    let token = comments
+        .iter()
+        .find(|token| line[indent..].starts_with(token.as_str()))
+        .map_or(0, String::len);
+    let marker = indent + token;
+    let rest = &line[marker..];
+    let prefix = marker + rest.len() - rest.trim_start().len();
+    (&line[..marker], &line[..prefix], &line[prefix..])
+}
+
+/// The first and last rows of the paragraph around row `y`: the rows next to
+/// it that have the same indentation and comment token and text after them.
+pub fn paragraph(document: &Document, y: usize, comments: &[String]) -> Option<(usize, usize)> {
+    let belongs = |y: usize, marker: &str| {
+        document.row(y).is_some_and(|row| {
+            let (other, _, text) = split_prefix(row.as_str(), comments);
+            other == marker && !text.is_empty()
+        })
+    };
+    let row = document.row(y)?;
+    let (marker, _, text) = split_prefix(row.as_str(), comments);
+    if text.is_empty() {
+        return None;
+    }
+    let mut first = y;
+    while first > 0 && belongs(first - 1, marker) {
+        first -= 1;
+    }
+    let mut last = y;
+    while belongs(last + 1, marker) {
+        last += 1;
+    }
+    Some((first, last))
+}
+
+/// Rewraps the paragraphs in `lines` so that they fit in `width` display
+/// columns where possible, each line starting with the prefix of the first
+/// line of its paragraph. Lines without text separate paragraphs and are kept.
+pub fn fill(lines: &[&str], comments: &[String], width: usize, tab_width: usize) -> Vec<String> {
+    let mut filled = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let (marker, prefix, text) = split_prefix(lines[index], comments);
+        if text.is_empty() {
+            filled.push(lines[index].trim_end().to_string());
+            index += 1;
+            continue;
+        }
+        let mut words = Vec::new();
+        while let Some(line) = lines.get(index) {
+            let (other, _, text) = split_prefix(line, comments);
+            if other != marker || text.is_empty() {
+                break;
+            }
+            words.extend(text.split_whitespace());
+            index += 1;
+        }
+
+        let prefix_width = display_width(prefix, tab_width);
+        let mut line = prefix.to_string();
+        let mut line_width = prefix_width;
+        for (count, word) in words.into_iter().enumerate() {
+            let word_width = display_width(word, tab_width);
+            if count > 0 && line_width + 1 + word_width > width {
+                filled.push(line);
+                line = prefix.to_string();
+                line_width = prefix_width;
+            } else if count > 0 {
+                line.push(' ');
+                line_width += 1;
+            }
+            line.push_str(word);
+            line_width += word_width;
+        }
+        filled.push(line);
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comments() -> Vec<String> {
+        vec!["///".to_string(), "//".to_string()]
+    }
+
+    #[test]
+    fn fills_paragraphs_to_the_width() {
+        let lines = ["one two three", "four five", "", "six seven eight nine"];
+        assert_eq!(
+            fill(&lines, &comments(), 10, 4),
+            [
+                "one two",
+                "three four",
+                "five",
+                "",
+                "six seven",
+                "eight nine"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_prefix_of_comments() {
+        let lines = [
+            "    // alpha beta",
+            "    // gamma delta epsilon",
+            "    /// zeta",
+        ];
+        assert_eq!(
+            fill(&lines, &comments(), 20, 4),
+            [
+                "    // alpha beta",
+                "    // gamma delta",
+                "    // epsilon",
+                "    /// zeta",
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_words_longer_than_the_width() {
+        let lines = ["\tsupercalifragilistic word"];
+        assert_eq!(
+            fill(&lines, &comments(), 8, 4),
+            ["\tsupercalifragilistic", "\tword"]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use crate::editor::SearchDirection;
+use crate::search::Query;
+use crate::Document;
+use crate::Position;
+
+/// A replace in progress: steps through the matches of `query` from a
+/// position, replacing those it is told to with `template`, with `$1` and
+/// the like expanded. All of its edits are undone as one.
+pub struct Replacement<'a> {
+    query: &'a Query,
+    template: &'a str,
+    /// Where to look for the next match.
+    at: Position,
+    /// Where the last match, or its replacement, ended.
+    previous_end: Option<Position>,
+    count: usize,
+}
+
+impl<'a> Replacement<'a> {
+    pub fn new(
+        document: &mut Document,
+        query: &'a Query,
+        template: &'a str,
+        from: Position,
+    ) -> Self {
+        document.begin_undo_group();
+        Self {
+            query,
+            template,
+            at: from,
+            previous_end: None,
+            count: 0,
+        }
+    }
+
+    /// The next match to offer, as a start and an end.
+    pub fn next_match(&mut self, document: &Document) -> Option<(Position, Position)> {
+        loop {
+            let (start, end) =
+                document.find_match(self.query, &self.at, SearchDirection::Forward)?;
+            // An empty match right after the last one would be found again
+            // and again, so step past it.
+            if (start.x, start.y) == (end.x, end.y)
+                && self
+                    .previous_end
+                    .as_ref()
+                    .is_some_and(|previous| (previous.x, previous.y) == (start.x, start.y))
+            {
+                self.at = Position {
+                    x: start.x.saturating_add(1),
+                    y: start.y,
+                };
+                self.previous_end = None;
+                continue;
+            }
+            return Some((start, end));
+        }
+    }
+
+    /// Leaves the match ending at `end` as it is.
+    pub fn skip(&mut self, end: &Position) {
+        self.at = end.clone();
+        self.previous_end = Some(end.clone());
+    }
+
+    /// Replaces the match from `start` to `end`.
+    pub fn replace(&mut self, document: &mut Document, start: &Position, end: &Position) {
+        let replacement = document.expand_match(self.query, start, self.template);
+        self.at = document.replace(start, end, &replacement);
+        self.previous_end = Some(self.at.clone());
+        self.count += 1;
+    }
+
+    /// Replaces every remaining match, returning where the last one started.
+    pub fn replace_all(&mut self, document: &mut Document) -> Option<Position> {
+        let mut last = None;
+        while let Some((start, end)) = self.next_match(document) {
+            self.replace(document, &start, &end);
+            last = Some(start);
+        }
+        last
+    }
+
+    /// Ends the replace, returning how many matches were replaced.
+    pub fn finish(self, document: &mut Document) -> usize {
+        document.end_undo_group();
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Options;
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::default();
+        document.replace(&Position::default(), &Position::default(), text);
+        document
+    }
+
+    fn text(document: &Document) -> String {
+        document.text(&Position::default(), &document.end_position())
+    }
+
+    fn regex(pattern: &str) -> Query {
+        let options = Options {
+            regex: true,
+            ..Options::default()
+        };
+        Query::new(pattern, options).unwrap()
+    }
+
+    fn replace_all(text: &str, query: &Query, template: &str) -> (Document, usize) {
+        let mut document = document(text);
+        let mut replacement = Replacement::new(&mut document, query, template, Position::default());
+        replacement.replace_all(&mut document);
+        let count = replacement.finish(&mut document);
+        (document, count)
+    }
+
+    #[test]
+    fn replaces_all_matches_as_one_undo_step() {
+        let (mut document, count) = replace_all("a1 b2\nc3", &regex(r"(\w)(\d)"), "$2$1");
+        assert_eq!(count, 3);
+        assert_eq!(text(&document), "1a 2b\n3c");
+        document.undo();
+        assert_eq!(text(&document), "a1 b2\nc3");
+        document.redo();
+        assert_eq!(text(&document), "1a 2b\n3c");
+    }
+
+    #[test]
+    fn expands_groups_of_matches_across_rows() {
+        let (document, count) = replace_all("foo\nbar baz\nqux", &regex(r"(\w+)\n(\w+)"), "$2 $1");
+        assert_eq!(count, 2);
+        assert_eq!(text(&document), "bar foo qux baz");
+    }
+
+    #[test]
+    fn replaces_each_empty_match_once() {
+        let (document, count) = replace_all("ab", &regex("x*"), "-");
+        assert_eq!(count, 3);
+        assert_eq!(text(&document), "-a-b-");
+    }
+
+    #[test]
+    fn skips_matches_it_is_not_told_to_replace() {
+        let mut document = document("a a a");
+        let query = Query::new("a", Options::default()).unwrap();
+        let mut replacement = Replacement::new(&mut document, &query, "b", Position::default());
+        let (start, end) = replacement.next_match(&document).unwrap();
+        replacement.skip(&end);
+        assert_eq!(start.x, 0);
+        let (start, end) = replacement.next_match(&document).unwrap();
+        replacement.replace(&mut document, &start, &end);
+        assert_eq!(start.x, 2);
+        assert_eq!(replacement.finish(&mut document), 1);
+        assert_eq!(text(&document), "a b a");
+    }
+}
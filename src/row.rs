@@ -1,13 +1,11 @@
-use crate::highlighting::Mode;
+use crate::highlighting::{Mode, Open, SELECTION_BG_COLOR};
 use crate::HighlightOptions;
-use crate::SearchDirection;
-use std::cmp::min;
 use termion::color;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 /*
 TODO:
-- Add tests, particularly around highlighting computations.
 - The loop in highlight implicitly relies on the highlight functions to advance
   index. If any of these functions returns true, but does not modify index, we run
   into an infinite loop. This is not obvious in the code and therefore not ideal.
@@ -23,18 +21,36 @@ pub struct Row {
     len: usize,
     highlighting: Vec<Mode>,
     pub is_highlighted: bool,
+    /// The search matches the row was last highlighted with.
+    matches: Vec<(usize, usize)>,
+    /// The block comment or string still open at the end of the row when it
+    /// was last highlighted.
+    open: Option<Open>,
     string: String,
 }
 
 impl Row {
     pub fn append(&mut self, new: &Self) {
         self.string = format!("{}{}", self.string, new.string);
+        self.len += new.len;
+        self.is_highlighted = false;
     }
 
     pub fn as_bytes(&self) -> &[u8] {
         self.string.as_bytes()
     }
 
+    pub fn as_str(&self) -> &str {
+        &self.string
+    }
+
+    pub fn char_at(&self, at: usize) -> Option<char> {
+        self.string[..]
+            .graphemes(true)
+            .nth(at)
+            .and_then(|grapheme| grapheme.chars().next())
+    }
+
     pub fn delete(&mut self, at: usize) {
         if at >= self.len() {
             return;
@@ -51,102 +67,464 @@ impl Row {
         self.string = result;
     }
 
-    pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
-        if at > self.len || query.is_empty() {
-            return None;
+    /// Highlights the row, starting inside the block comment or string
+    /// `open` if the previous row ended in one, with the search `matches` in
+    /// it. Returns the block comment or string still open at the end of the
+    /// row.
+    pub fn highlight(
+        &mut self,
+        opts: &HighlightOptions,
+        matches: &[(usize, usize)],
+        open: Option<Open>,
+    ) -> Option<Open> {
+        let chars: Vec<char> = self.string.chars().collect();
+        if self.is_highlighted && self.matches == matches {
+            return self.open;
         }
-        let start = if direction == SearchDirection::Forward {
-            at
-        } else {
-            0
-        };
-        let end = if direction == SearchDirection::Forward {
-            self.len
-        } else {
-            at
-        };
+        self.highlighting = Vec::new();
+        let mut index = 0;
+        let mut open = open.filter(|&open| !self.close(&mut index, opts, &chars, open));
 
-        let substring: String = self.string[..]
-            .graphemes(true)
-            .skip(start)
-            .take(end - start)
-            .collect();
-        let matching_byte_index = if direction == SearchDirection::Forward {
-            substring.find(query)
-        } else {
-            substring.rfind(query)
-        };
-        if let Some(matching_byte_index) = matching_byte_index {
-            for (grapheme_index, (byte_index, _)) in
-                substring[..].grapheme_indices(true).enumerate()
+        while let Some(c) = chars.get(index) {
+            if self.highlight_multiline_comments(&mut index, opts, &chars, &mut open)
+                || self.highlight_code_block(&mut index, opts, &chars, &mut open)
+                || self.highlight_heading(&mut index, opts, *c, &chars)
+                || self.highlight_block_quote(&mut index, opts, *c, &chars)
+                || self.highlight_list_marker(&mut index, opts, &chars)
+                || self.highlight_code_span(&mut index, opts, *c, &chars)
+                || self.highlight_link(&mut index, opts, *c, &chars)
+                || self.highlight_emphasis(&mut index, opts, &chars)
+                || self.highlight_section(&mut index, opts, &chars)
+                || self.highlight_key(&mut index, opts, &chars)
+                || self.highlight_block_scalar(&mut index, opts, *c, &chars, &mut open)
+                || self.highlight_anchor(&mut index, opts, *c, &chars)
+                || self.highlight_decorator(&mut index, opts, *c, &chars)
+                || self.highlight_preprocessor(&mut index, opts, *c, &chars)
+                || self.highlight_char(&mut index, opts, *c, &chars)
+                || self.highlight_comment(&mut index, opts, &chars)
+                || self.highlight_primary_keywords(&mut index, opts, &chars)
+                || self.highlight_secondary_keywords(&mut index, opts, &chars)
+                || self.highlight_string(&mut index, opts, &chars, &mut open)
+                || self.highlight_number(&mut index, opts, *c, &chars)
             {
-                if matching_byte_index == byte_index {
-                    return Some(start + grapheme_index);
-                }
+                continue;
             }
+            self.highlighting.push(Mode::None);
+            index += 1;
         }
-        None
+        self.highlight_match(matches);
+        self.matches = matches.to_vec();
+        self.open = open;
+        self.is_highlighted = true;
+        open
     }
 
-    pub fn highlight(
+    /// Highlights up to the end of the block comment, string, code block or
+    /// block scalar `open`, returning whether it ends on this row. A block
+    /// scalar ends before the first line that is not blank and not indented
+    /// past the line that started it.
+    fn close(
         &mut self,
+        index: &mut usize,
         opts: &HighlightOptions,
-        word: &Option<String>,
-        start_with_comment: bool,
+        chars: &[char],
+        open: Open,
     ) -> bool {
-        let chars: Vec<char> = self.string.chars().collect();
-        if self.is_highlighted && word.is_none() {
-            if let Some(hl_type) = self.highlighting.last() {
-                if *hl_type == Mode::MultilineComment
-                    && self.string.len() > 1
-                    && self.string[self.string.len() - 2..] == *"*/"
-                {
+        match open {
+            Open::Comment(comment) => opts.block_comments.get(comment).is_none_or(|(_, end)| {
+                self.highlight_until(index, chars, end, None, Mode::MultilineComment)
+            }),
+            Open::String(string) => opts.strings.get(string).is_none_or(|rule| {
+                self.highlight_until(index, chars, &rule.delimiter, rule.escape, Mode::String)
+            }),
+            Open::CodeBlock(block) => opts.code_blocks.get(block).is_none_or(|(_, end)| {
+                let indentation = chars.iter().take_while(|c| c.is_whitespace()).count();
+                self.highlight_len(index, chars.len() - *index, Mode::Code);
+                starts_with(&chars[indentation..], end)
+            }),
+            Open::BlockScalar(indentation) => {
+                let line_indentation = chars.iter().take_while(|c| c.is_whitespace()).count();
+                if line_indentation < chars.len() && line_indentation <= indentation {
                     return true;
                 }
+                self.highlight_len(index, chars.len() - *index, Mode::String);
+                false
             }
+        }
+    }
+
+    /// Highlights a decorator: its character at the start of the line, after
+    /// any indentation, and the dotted name that follows it.
+    fn highlight_decorator(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if opts.decorators != Some(c) || !is_line_start(chars, *index) {
             return false;
         }
-        self.highlighting = Vec::new();
-        let mut index = 0;
-        let mut in_ml_comment = start_with_comment;
-        if in_ml_comment {
-            let closing_index = if let Some(closing_index) = self.string.find("*/") {
-                closing_index + 2
-            } else {
-                chars.len()
-            };
-            for _ in 0..closing_index {
-                self.highlighting.push(Mode::MultilineComment);
+        loop {
+            self.highlighting.push(Mode::Decorator);
+            *index += 1;
+            match chars.get(*index) {
+                Some(next_char)
+                    if next_char.is_alphanumeric() || matches!(next_char, '_' | '.') => {}
+                _ => break,
             }
-            index = closing_index;
         }
+        true
+    }
 
-        while let Some(c) = chars.get(index) {
-            if self.highlight_multiline_comments(&mut index, opts, *c, &chars) {
-                in_ml_comment = true;
-                continue;
+    /// Highlights a preprocessor directive at the start of the line, after
+    /// any indentation, and the `<path>` that follows it, as in `#include`.
+    fn highlight_preprocessor(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if opts.preprocessor != Some(c) || !is_line_start(chars, *index) {
+            return false;
+        }
+        self.highlighting.push(Mode::Preprocessor);
+        *index += 1;
+        while chars.get(*index).is_some_and(|c| c.is_whitespace()) {
+            self.highlighting.push(Mode::Preprocessor);
+            *index += 1;
+        }
+        while chars
+            .get(*index)
+            .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+        {
+            self.highlighting.push(Mode::Preprocessor);
+            *index += 1;
+        }
+        while chars.get(*index).is_some_and(|c| c.is_whitespace()) {
+            self.highlighting.push(Mode::None);
+            *index += 1;
+        }
+        if self.highlight_str(index, "<", chars, Mode::String) {
+            self.highlight_until(index, chars, ">", None, Mode::String);
+        }
+        true
+    }
+
+    /// Highlights the opening fence of a code block, and the rest of its line,
+    /// setting `open` to it.
+    fn highlight_code_block(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        chars: &[char],
+        open: &mut Option<Open>,
+    ) -> bool {
+        if !is_line_start(chars, *index) {
+            return false;
+        }
+        for (block, (start, _)) in opts.code_blocks.iter().enumerate() {
+            if starts_with(&chars[*index..], start) {
+                self.highlight_len(index, chars.len() - *index, Mode::Code);
+                *open = Some(Open::CodeBlock(block));
+                return true;
             }
-            in_ml_comment = false;
-            if self.highlight_char(&mut index, opts, *c, &chars)
-                || self.highlight_comment(&mut index, opts, *c, &chars)
-                || self.highlight_primary_keywords(&mut index, opts, &chars)
-                || self.highlight_secondary_keywords(&mut index, opts, &chars)
-                || self.highlight_string(&mut index, opts, *c, &chars)
-                || self.highlight_number(&mut index, opts, *c, &chars)
+        }
+        false
+    }
+
+    /// Highlights a heading: a run of its character at the start of the line,
+    /// followed by whitespace, and the rest of the line.
+    fn highlight_heading(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if opts.headings != Some(c) || !is_line_start(chars, *index) {
+            return false;
+        }
+        let level = chars[*index..]
+            .iter()
+            .take_while(|&&next| next == c)
+            .count();
+        if !chars
+            .get(*index + level)
+            .is_none_or(|next| next.is_whitespace())
+        {
+            return false;
+        }
+        self.highlight_len(index, chars.len() - *index, Mode::Heading);
+        true
+    }
+
+    /// Highlights a block quote, from its character at the start of the line
+    /// to the end of the line.
+    fn highlight_block_quote(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if opts.block_quotes != Some(c) || !is_line_start(chars, *index) {
+            return false;
+        }
+        self.highlight_len(index, chars.len() - *index, Mode::Quote);
+        true
+    }
+
+    /// Highlights a list marker, or the number of an ordered list item, at
+    /// the start of the line and followed by whitespace.
+    fn highlight_list_marker(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        chars: &[char],
+    ) -> bool {
+        if !is_line_start(chars, *index) {
+            return false;
+        }
+        let len = opts
+            .list_markers
+            .iter()
+            .find(|marker| starts_with(&chars[*index..], marker))
+            .map(|marker| marker.chars().count())
+            .or_else(|| {
+                let digits = chars[*index..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+                let is_number = digits > 0 && matches!(chars.get(*index + digits), Some('.' | ')'));
+                (opts.ordered_lists && is_number).then_some(digits + 1)
+            });
+        match len {
+            Some(len)
+                if len > 0
+                    && chars
+                        .get(*index + len)
+                        .is_none_or(|next| next.is_whitespace()) =>
             {
-                continue;
+                self.highlight_len(index, len, Mode::ListMarker);
+                true
             }
-            self.highlighting.push(Mode::None);
-            index += 1;
+            _ => false,
         }
-        self.highlight_match(word);
-        if in_ml_comment && &self.string[self.string.len().saturating_sub(2)..] != "*/" {
-            return true;
+    }
+
+    /// Highlights inline code: a run of its character up to the next run of
+    /// the same length on the row.
+    fn highlight_code_span(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if opts.code_spans != Some(c) {
+            return false;
+        }
+        let len = chars[*index..]
+            .iter()
+            .take_while(|&&next| next == c)
+            .count();
+        let fence = c.to_string().repeat(len);
+        let Some(end) = find(&chars[*index + len..], &fence) else {
+            return false;
+        };
+        self.highlight_len(index, len + end + len, Mode::Code);
+        true
+    }
+
+    /// Highlights a link, `[text](target)`.
+    fn highlight_link(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if !opts.links || c != '[' {
+            return false;
+        }
+        let Some(text_end) = find(&chars[*index..], "](") else {
+            return false;
+        };
+        let Some(target_end) = find(&chars[*index + text_end..], ")") else {
+            return false;
+        };
+        self.highlight_len(index, text_end + target_end + 1, Mode::Link);
+        true
+    }
+
+    /// Highlights emphasis, or strong emphasis when its delimiter is doubled,
+    /// if it is closed later on the row.
+    fn highlight_emphasis(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        chars: &[char],
+    ) -> bool {
+        if *index > 0 && !is_separator(chars[*index - 1]) {
+            return false;
+        }
+        for delimiter in &opts.emphasis {
+            let strong = delimiter.repeat(2);
+            for (delimiter, hl_type) in [(&strong, Mode::Strong), (delimiter, Mode::Emphasis)] {
+                if !starts_with(&chars[*index..], delimiter) {
+                    continue;
+                }
+                let len = delimiter.chars().count();
+                if len == 0
+                    || chars
+                        .get(*index + len)
+                        .is_none_or(|next| next.is_whitespace())
+                {
+                    continue;
+                }
+                if let Some(end) = find(&chars[*index + len..], delimiter) {
+                    self.highlight_len(index, len + end + len, hl_type);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Highlights a section header at the start of the line, like TOML's
+    /// `[table]`.
+    fn highlight_section(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        chars: &[char],
+    ) -> bool {
+        if !is_line_start(chars, *index) {
+            return false;
+        }
+        for (start, end) in &opts.sections {
+            let start_len = start.chars().count();
+            if !starts_with(&chars[*index..], start) {
+                continue;
+            }
+            let Some(len) = find(&chars[*index + start_len..], end) else {
+                continue;
+            };
+            let len = start_len + len + end.chars().count();
+            if len > 0 {
+                self.highlight_len(index, len, Mode::Section);
+                return true;
+            }
         }
-        self.is_highlighted = true;
         false
     }
 
+    /// Highlights a key followed by a key separator: a string, or a bare word
+    /// after nothing but indentation and list markers.
+    fn highlight_key(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        chars: &[char],
+    ) -> bool {
+        if opts.key_separators.is_empty() {
+            return false;
+        }
+        let len = if let Some(string) = string_at(opts, &chars[*index..]) {
+            let rule = &opts.strings[string];
+            let delimiter_len = rule.delimiter.chars().count();
+            match closing(
+                &chars[*index + delimiter_len..],
+                &rule.delimiter,
+                rule.escape,
+            ) {
+                Some(len) => delimiter_len + len,
+                None => return false,
+            }
+        } else if chars[..*index]
+            .iter()
+            .zip(&self.highlighting)
+            .all(|(c, mode)| c.is_whitespace() || *mode == Mode::ListMarker)
+        {
+            chars[*index..]
+                .iter()
+                .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+                .count()
+        } else {
+            0
+        };
+        if len == 0 {
+            return false;
+        }
+        let spaces = chars[*index + len..]
+            .iter()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        if !opts
+            .key_separators
+            .iter()
+            .any(|separator| starts_with(&chars[*index + len + spaces..], separator))
+        {
+            return false;
+        }
+        self.highlight_len(index, len, Mode::Key);
+        true
+    }
+
+    /// Highlights a block scalar indicator after a key separator, like the
+    /// `|` of YAML's `key: |`, setting `open` to the block that follows.
+    fn highlight_block_scalar(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        c: char,
+        chars: &[char],
+        open: &mut Option<Open>,
+    ) -> bool {
+        if !opts.block_scalars.contains(c) {
+            return false;
+        }
+        let before: String = chars[..*index].iter().collect();
+        let after_key = opts
+            .key_separators
+            .iter()
+            .any(|separator| before.trim_end().ends_with(separator.trim_end()));
+        let only_modifiers = chars[*index + 1..]
+            .iter()
+            .all(|c| c.is_ascii_digit() || c.is_whitespace() || matches!(c, '-' | '+'));
+        if !after_key || !only_modifiers {
+            return false;
+        }
+        let indentation = chars.iter().take_while(|c| c.is_whitespace()).count();
+        self.highlight_len(index, chars.len() - *index, Mode::String);
+        *open = Some(Open::BlockScalar(indentation));
+        true
+    }
+
+    /// Highlights an anchor or alias, like YAML's `&name` and `*name`.
+    fn highlight_anchor(
+        &mut self,
+        index: &mut usize,
+        opts: &HighlightOptions,
+        c: char,
+        chars: &[char],
+    ) -> bool {
+        if !opts.anchors.contains(c) || (*index > 0 && !chars[*index - 1].is_whitespace()) {
+            return false;
+        }
+        let len = chars[*index + 1..]
+            .iter()
+            .take_while(|c| !c.is_whitespace() && !matches!(c, ',' | '[' | ']' | '{' | '}'))
+            .count();
+        if len == 0 {
+            return false;
+        }
+        self.highlight_len(index, len + 1, Mode::Anchor);
+        true
+    }
+
     fn highlight_char(
         &mut self,
         index: &mut usize,
@@ -154,7 +532,7 @@ impl Row {
         c: char,
         chars: &[char],
     ) -> bool {
-        if opts.characters() && c == '\'' {
+        if opts.characters == Some(c) {
             if let Some(next_char) = chars.get(index.saturating_add(1)) {
                 let closing_index = if *next_char == '\\' {
                     index.saturating_add(3)
@@ -162,7 +540,7 @@ impl Row {
                     index.saturating_add(2)
                 };
                 if let Some(closing_char) = chars.get(closing_index) {
-                    if *closing_char == '\'' {
+                    if *closing_char == c {
                         for _ in 0..=closing_index.saturating_sub(*index) {
                             self.highlighting.push(Mode::Character);
                             *index += 1;
@@ -174,23 +552,23 @@ impl Row {
         }
         false
     }
+
     fn highlight_comment(
         &mut self,
         index: &mut usize,
         opts: &HighlightOptions,
-        c: char,
         chars: &[char],
     ) -> bool {
-        if opts.comments() && c == '/' && *index < chars.len() {
-            if let Some(next_char) = chars.get(index.saturating_add(1)) {
-                if *next_char == '/' {
-                    for _ in *index..chars.len() {
-                        self.highlighting.push(Mode::Comment);
-                        *index += 1;
-                    }
-                    return true;
-                };
+        if opts
+            .line_comments
+            .iter()
+            .any(|token| starts_with(&chars[*index..], token))
+        {
+            for _ in *index..chars.len() {
+                self.highlighting.push(Mode::Comment);
+                *index += 1;
             }
+            return true;
         }
         false
     }
@@ -222,49 +600,31 @@ impl Row {
         false
     }
 
-    pub fn highlight_match(&mut self, word: &Option<String>) {
-        if let Some(word) = word {
-            if word.is_empty() {
-                return;
-            }
-            let mut index = 0;
-            while let Some(search_match) = self.find(word, index, SearchDirection::Forward) {
-                if let Some(next_index) = search_match.checked_add(word[..].graphemes(true).count())
-                {
-                    for i in index.saturating_add(search_match)..next_index {
-                        self.highlighting[i] = Mode::Match;
-                    }
-                    index = next_index;
-                } else {
-                    break;
-                }
+    /// Highlights the given ranges of graphemes as search matches.
+    pub fn highlight_match(&mut self, matches: &[(usize, usize)]) {
+        for &(start, end) in matches {
+            for mode in self.highlighting.iter_mut().take(end).skip(start) {
+                *mode = Mode::Match;
             }
         }
     }
 
+    /// Highlights a block comment starting at `index`, setting `open` to it
+    /// if it does not end on this row.
     fn highlight_multiline_comments(
         &mut self,
         index: &mut usize,
         opts: &HighlightOptions,
-        c: char,
         chars: &[char],
+        open: &mut Option<Open>,
     ) -> bool {
-        if opts.comments() && c == '/' && *index < chars.len() {
-            if let Some(next_char) = chars.get(index.saturating_add(1)) {
-                if *next_char == '*' {
-                    let closing_index =
-                        if let Some(closing_index) = self.string[*index + 2..].find("*/") {
-                            *index + closing_index + 4
-                        } else {
-                            chars.len()
-                        };
-                    for _ in *index..closing_index {
-                        self.highlighting.push(Mode::MultilineComment);
-                        *index += 1;
-                    }
-                    return true;
+        for (comment, (start, _)) in opts.block_comments.iter().enumerate() {
+            if self.highlight_str(index, start, chars, Mode::MultilineComment) {
+                if !self.close(index, opts, chars, Open::Comment(comment)) {
+                    *open = Some(Open::Comment(comment));
                 }
-            };
+                return true;
+            }
         }
         false
     }
@@ -276,27 +636,52 @@ impl Row {
         c: char,
         chars: &[char],
     ) -> bool {
-        if opts.numbers() && c.is_ascii_digit() {
-            if *index > 0 {
-                let prev_char = chars[*index - 1];
-                if !is_separator(prev_char) {
-                    return false;
-                }
+        let Some(rules) = &opts.numbers else {
+            return false;
+        };
+        if !c.is_ascii_digit() {
+            return false;
+        }
+        if *index > 0 {
+            let prev_char = chars[*index - 1];
+            if !is_separator(prev_char) {
+                return false;
             }
-            loop {
+        }
+        let prefix = rules
+            .prefixes
+            .iter()
+            .find(|prefix| starts_with(&chars[*index..], prefix));
+        if let Some(prefix) = prefix {
+            for _ in prefix.chars() {
                 self.highlighting.push(Mode::Number);
                 *index += 1;
-                if let Some(next_char) = chars.get(*index) {
-                    if *next_char != '.' && !next_char.is_ascii_digit() {
-                        break;
-                    }
-                } else {
+            }
+            while chars.get(*index).is_some_and(char::is_ascii_alphanumeric) {
+                self.highlighting.push(Mode::Number);
+                *index += 1;
+            }
+            return true;
+        }
+        loop {
+            self.highlighting.push(Mode::Number);
+            *index += 1;
+            if let Some(next_char) = chars.get(*index) {
+                if !rules.continuation.contains(*next_char) && !next_char.is_ascii_digit() {
                     break;
                 }
+            } else {
+                break;
             }
-            return true;
         }
-        false
+        while chars
+            .get(*index)
+            .is_some_and(|c| rules.suffixes.contains(*c))
+        {
+            self.highlighting.push(Mode::Number);
+            *index += 1;
+        }
+        true
     }
 
     fn highlight_primary_keywords(
@@ -305,7 +690,7 @@ impl Row {
         opts: &HighlightOptions,
         chars: &[char],
     ) -> bool {
-        self.highlight_keywords(index, chars, opts.primary_keywords(), Mode::PrimaryKeyword)
+        self.highlight_keywords(index, chars, &opts.primary_keywords, Mode::PrimaryKeyword)
     }
 
     fn highlight_secondary_keywords(
@@ -317,7 +702,7 @@ impl Row {
         self.highlight_keywords(
             index,
             chars,
-            opts.secondary_keywords(),
+            &opts.secondary_keywords,
             Mode::SecondaryKeyword,
         )
     }
@@ -342,40 +727,106 @@ impl Row {
                 return false;
             }
         }
-        for _ in 0..substring.len() {
+        for _ in 0..substring.chars().count() {
             self.highlighting.push(hl_type);
             *index += 1;
         }
         true
     }
 
+    /// Highlights a string starting at `index`, along with any prefix right
+    /// before its delimiter, setting `open` to it if it spans lines and does
+    /// not end on this row.
     fn highlight_string(
         &mut self,
         index: &mut usize,
         opts: &HighlightOptions,
-        c: char,
         chars: &[char],
+        open: &mut Option<Open>,
     ) -> bool {
-        if opts.strings() && c == '"' {
-            loop {
-                self.highlighting.push(Mode::String);
-                *index += 1;
-
-                if let Some(next_char) = chars.get(*index) {
-                    if *next_char == '"' {
-                        break;
-                    }
-                } else {
-                    break;
-                }
-            }
+        let after_separator = *index == 0 || is_separator(chars[*index - 1]);
+        let prefix_len = opts
+            .string_prefixes
+            .iter()
+            .filter(|prefix| after_separator && starts_with_ignore_case(&chars[*index..], prefix))
+            .map(|prefix| prefix.chars().count())
+            .find(|&len| string_at(opts, &chars[*index + len..]).is_some())
+            .unwrap_or(0);
+        let Some(string) = string_at(opts, &chars[*index + prefix_len..]) else {
+            return false;
+        };
+        for _ in 0..prefix_len {
             self.highlighting.push(Mode::String);
             *index += 1;
-            return true;
+        }
+        let rule = &opts.strings[string];
+        self.highlight_str(index, &rule.delimiter, chars, Mode::String);
+        if !self.close(index, opts, chars, Open::String(string)) && rule.multiline {
+            *open = Some(Open::String(string));
+        }
+        true
+    }
+
+    /// Highlights the next `len` characters.
+    fn highlight_len(&mut self, index: &mut usize, len: usize, hl_type: Mode) {
+        for _ in 0..len {
+            self.highlighting.push(hl_type);
+            *index += 1;
+        }
+    }
+
+    /// Highlights up to and including the next `end`, skipping characters
+    /// after `escape`, or to the end of the row if there is none. Returns
+    /// whether `end` was found.
+    fn highlight_until(
+        &mut self,
+        index: &mut usize,
+        chars: &[char],
+        end: &str,
+        escape: Option<char>,
+        hl_type: Mode,
+    ) -> bool {
+        while let Some(c) = chars.get(*index) {
+            if self.highlight_str(index, end, chars, hl_type) {
+                return true;
+            }
+            let len = if Some(*c) == escape { 2 } else { 1 };
+            for _ in 0..len.min(chars.len() - *index) {
+                self.highlighting.push(hl_type);
+                *index += 1;
+            }
         }
         false
     }
 
+    /// The byte offset of grapheme `index`, or the length of the row past its
+    /// end.
+    pub fn byte_index(&self, index: usize) -> usize {
+        self.string
+            .grapheme_indices(true)
+            .nth(index)
+            .map_or(self.string.len(), |(byte, _)| byte)
+    }
+
+    /// Converts a range of bytes to the graphemes that contain them.
+    pub fn grapheme_range(&self, start: usize, end: usize) -> (usize, usize) {
+        let mut first = self.len;
+        let mut last = self.len;
+        for (index, (byte, _)) in self.string.grapheme_indices(true).enumerate() {
+            if byte <= start {
+                first = index;
+            }
+            if byte >= end {
+                last = index;
+                break;
+            }
+        }
+        if start >= self.string.len() {
+            first = self.len;
+        }
+        (first, last.max(first))
+    }
+
     pub fn insert(&mut self, at: usize, c: char) {
         if at >= self.len() {
             self.string.push(c);
@@ -405,17 +856,30 @@ impl Row {
         self.len
     }
 
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = min(end, self.string.len());
-        let start = min(start, end);
+    /// Renders the display columns from `start` to `end`, expanding tabs to
+    /// the next multiple of `tab_width` and drawing the graphemes within the
+    /// `selected` range on a highlighted background. Columns of a grapheme cut
+    /// off by `start` are drawn as spaces.
+    pub fn render(
+        &self,
+        start: usize,
+        end: usize,
+        selected: Option<(usize, usize)>,
+        tab_width: usize,
+    ) -> String {
         let mut result = String::new();
         let mut current_highlighting = &Mode::None;
-        for (index, grapheme) in self.string[..]
-            .graphemes(true)
-            .enumerate()
-            .skip(start)
-            .take(end - start)
-        {
+        let mut in_selection = false;
+        let mut column = 0;
+        for (index, grapheme) in self.string[..].graphemes(true).enumerate() {
+            let width = grapheme_width(grapheme, column, tab_width);
+            if column >= end || (column + width > end && grapheme != "\t") {
+                break;
+            }
+            column += width;
+            if column <= start {
+                continue;
+            }
             if let Some(c) = grapheme.chars().next() {
                 let highlighting_type = self.highlighting.get(index).unwrap_or(&Mode::None);
                 if highlighting_type != current_highlighting {
@@ -423,18 +887,43 @@ impl Row {
                     let start_highlight = format!("{}", color::Fg(highlighting_type.to_color()));
                     result.push_str(&start_highlight[..]);
                 }
-                if c == '\t' {
-                    result.push(' ');
+                let is_selected = selected.is_some_and(|(from, to)| from <= index && index < to);
+                if is_selected != in_selection {
+                    in_selection = is_selected;
+                    let background = if is_selected {
+                        format!("{}", color::Bg(SELECTION_BG_COLOR))
+                    } else {
+                        format!("{}", color::Bg(color::Reset))
+                    };
+                    result.push_str(&background[..]);
+                }
+                if c == '\t' || column - width < start {
+                    let visible = width.min(column - start).min(end + width - column);
+                    result.push_str(&" ".repeat(visible));
                 } else {
-                    result.push(c);
+                    result.push_str(grapheme);
                 }
             }
         }
-        let end_highlight = format!("{}", color::Fg(color::Reset));
+        let end_highlight = format!("{}{}", color::Fg(color::Reset), color::Bg(color::Reset));
         result.push_str(&end_highlight[..]);
         result
     }
 
+    /// The display column of grapheme `x`, counting tabs as reaching the
+    /// next multiple of `tab_width`.
+    pub fn render_x(&self, x: usize, tab_width: usize) -> usize {
+        display_width(&self.slice(0, x), tab_width) + x.saturating_sub(self.len)
+    }
+
+    pub fn slice(&self, start: usize, end: usize) -> String {
+        self.string[..]
+            .graphemes(true)
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .collect()
+    }
+
     pub fn split(&mut self, at: usize) -> Self {
         let mut row = String::new();
         let mut length = 0;
@@ -456,6 +945,8 @@ impl Row {
             len: splitted_length,
             highlighting: Vec::new(),
             is_highlighted: false,
+            matches: Vec::new(),
+            open: None,
             string: splitted_row,
         }
     }
@@ -467,11 +958,335 @@ impl From<&str> for Row {
             len: slice.graphemes(true).count(),
             highlighting: Vec::new(),
             is_highlighted: false,
+            matches: Vec::new(),
+            open: None,
             string: String::from(slice),
         }
     }
 }
 
-fn is_separator(c: char) -> bool {
+/// The number of columns `text` takes on screen when it starts at the left
+/// edge, with tabs reaching the next multiple of `tab_width`.
+pub fn display_width(text: &str, tab_width: usize) -> usize {
+    text.graphemes(true).fold(0, |column, grapheme| {
+        column + grapheme_width(grapheme, column, tab_width)
+    })
+}
+
+/// The number of columns `grapheme` takes when drawn at `column`.
+fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        tab_width - column % tab_width
+    } else {
+        grapheme.width()
+    }
+}
+
+/// Whether `chars` starts with the characters of `prefix`.
+fn starts_with(chars: &[char], prefix: &str) -> bool {
+    let mut chars = chars.iter();
+    prefix.chars().all(|c| chars.next() == Some(&c))
+}
+
+/// Whether only whitespace comes before `index` in `chars`.
+fn is_line_start(chars: &[char], index: usize) -> bool {
+    chars[..index].iter().all(|c| c.is_whitespace())
+}
+
+/// Whether `chars` starts with the characters of `prefix`, ignoring ASCII
+/// case.
+fn starts_with_ignore_case(chars: &[char], prefix: &str) -> bool {
+    let mut chars = chars.iter();
+    prefix.chars().all(|c| {
+        chars
+            .next()
+            .is_some_and(|next| next.eq_ignore_ascii_case(&c))
+    })
+}
+
+/// Where `pattern` first starts in `chars`.
+fn find(chars: &[char], pattern: &str) -> Option<usize> {
+    (0..chars.len()).find(|&at| starts_with(&chars[at..], pattern))
+}
+
+/// Where `end` first ends in `chars`, skipping characters after `escape`.
+fn closing(chars: &[char], end: &str, escape: Option<char>) -> Option<usize> {
+    let mut at = 0;
+    while at < chars.len() {
+        if starts_with(&chars[at..], end) {
+            return Some(at + end.chars().count());
+        }
+        at += if Some(chars[at]) == escape { 2 } else { 1 };
+    }
+    None
+}
+
+/// The index of the string rule whose delimiter `chars` starts with.
+fn string_at(opts: &HighlightOptions, chars: &[char]) -> Option<usize> {
+    opts.strings
+        .iter()
+        .position(|rule| starts_with(chars, &rule.delimiter))
+}
+
+pub fn is_separator(c: char) -> bool {
     c.is_ascii_punctuation() || c.is_ascii_whitespace()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::{self, Syntax};
+
+    const RUST: &str = include_str!("../syntax/rust.toml");
+
+    /// One character for each highlighted character.
+    fn letter(mode: Mode) -> char {
+        match mode {
+            Mode::Anchor => '&',
+            Mode::Character => '\'',
+            Mode::Code => '`',
+            Mode::Comment => '/',
+            Mode::Decorator => '@',
+            Mode::Emphasis => 'e',
+            Mode::Heading => 'h',
+            Mode::Key => 'k',
+            Mode::Link => 'l',
+            Mode::ListMarker => '-',
+            Mode::Match => 'm',
+            Mode::MultilineComment => '*',
+            Mode::None => '.',
+            Mode::PrimaryKeyword => 'p',
+            Mode::Number => '0',
+            Mode::Preprocessor => '#',
+            Mode::Quote => '>',
+            Mode::Section => 's',
+            Mode::SecondaryKeyword => 't',
+            Mode::String => '"',
+            Mode::Strong => 'E',
+        }
+    }
+
+    fn options(contents: &str) -> HighlightOptions {
+        let syntax: &'static Syntax = Box::leak(Box::new(syntax::parse(contents).unwrap()));
+        HighlightOptions::from(syntax)
+    }
+
+    /// Highlights `lines` as consecutive rows with the definition in
+    /// `contents`, describing each with `letter`.
+    fn highlight(contents: &str, lines: &[&str]) -> Vec<String> {
+        let opts = options(contents);
+        let mut open = None;
+        lines
+            .iter()
+            .map(|line| {
+                let mut row = Row::from(*line);
+                open = row.highlight(&opts, &[], open);
+                row.highlighting.iter().map(|&mode| letter(mode)).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nests_strings_and_comments() {
+        assert_eq!(
+            highlight(RUST, &["let s = \"a /* b\"; // c", "/* \"x */ y"]),
+            ["ppp.....\"\"\"\"\"\"\"\"..////", "********.."]
+        );
+    }
+
+    #[test]
+    fn continues_strings_and_comments_on_the_next_rows() {
+        assert_eq!(
+            highlight(RUST, &["x = \"ab", "c\" + 'd' /* e", "f */ 1_000.5 0x1f"]),
+            ["....\"\"\"", "\"\"...'''.****", "****.0000000.0000"]
+        );
+    }
+
+    #[test]
+    fn highlights_again_when_the_matches_change() {
+        let opts = options(RUST);
+        let modes =
+            |row: &Row| -> String { row.highlighting.iter().map(|&mode| letter(mode)).collect() };
+        let mut row = Row::from("let x");
+        row.highlight(&opts, &[(4, 5)], None);
+        assert_eq!(modes(&row), "ppp.m");
+        row.highlight(&opts, &[(4, 5)], None);
+        assert_eq!(modes(&row), "ppp.m");
+        row.highlight(&opts, &[], None);
+        assert_eq!(modes(&row), "ppp..");
+    }
+
+    #[test]
+    fn highlights_python_decorators_and_prefixed_strings() {
+        assert_eq!(
+            highlight(
+                include_str!("../syntax/python.toml"),
+                &[
+                    "@decorator",
+                    "def f(): return f'x' # c",
+                    "s = \"\"\"a",
+                    "b\"\"\" 0x1f"
+                ]
+            ),
+            [
+                "@@@@@@@@@@",
+                "ppp......pppppp.\"\"\"\".///",
+                "....\"\"\"\"",
+                "\"\"\"\".0000",
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_c_preprocessor_directives() {
+        assert_eq!(
+            highlight(
+                include_str!("../syntax/c.toml"),
+                &["#include <stdio.h>", "#include \"a.h\"", "  #define N 10"]
+            ),
+            [
+                "########.\"\"\"\"\"\"\"\"\"",
+                "########.\"\"\"\"\"",
+                "..#######...00"
+            ]
+        );
+        assert_eq!(
+            highlight(include_str!("../syntax/cpp.toml"), &["#include <vector>"]),
+            ["########.\"\"\"\"\"\"\"\""]
+        );
+    }
+
+    #[test]
+    fn highlights_c_numbers_characters_and_prefixed_strings() {
+        assert_eq!(
+            highlight(
+                include_str!("../syntax/c.toml"),
+                &[
+                    "int x = 0x1Fu + 017 + 1.5e3f;",
+                    "char c = 'x'; u8\"s\" /* a",
+                    "b */ 10ul",
+                ]
+            ),
+            [
+                "ttt.....00000...000...000000.",
+                "tttt.....'''..\"\"\"\"\".****",
+                "****.0000",
+            ]
+        );
+        assert_eq!(
+            highlight(
+                include_str!("../syntax/cpp.toml"),
+                &["auto x = 0x1F + 017 + 0b101 + 1'000ull;"]
+            ),
+            ["pppp.....0000...000...00000...00000000."]
+        );
+    }
+
+    #[test]
+    fn highlights_markdown_blocks_and_spans() {
+        assert_eq!(
+            highlight(
+                include_str!("../syntax/markdown.toml"),
+                &[
+                    "# Title *x*",
+                    "> quote",
+                    "- item **bold** `code`",
+                    "1. [link](x) _em_",
+                    "```rust",
+                    "# not a heading",
+                    "```",
+                    "after *em*",
+                ]
+            ),
+            [
+                "hhhhhhhhhhh",
+                ">>>>>>>",
+                "-......EEEEEEEE.``````",
+                "--.lllllllll.eeee",
+                "```````",
+                "```````````````",
+                "```",
+                "......eeee",
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_toml_sections_and_multiline_strings() {
+        assert_eq!(
+            highlight(
+                include_str!("../syntax/toml.toml"),
+                &[
+                    "[[bin]]",
+                    "name = \"x\" # c",
+                    "[a.b]",
+                    "s = \"\"\"",
+                    "[not]",
+                    "\"\"\" 1979-05-27"
+                ]
+            ),
+            [
+                "sssssss",
+                "kkkk...\"\"\".///",
+                "sssss",
+                "k...\"\"\"",
+                "\"\"\"\"\"",
+                "\"\"\".0000000000",
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_yaml_block_scalars_and_anchors() {
+        assert_eq!(
+            highlight(
+                include_str!("../syntax/yaml.toml"),
+                &[
+                    "key: |",
+                    "  text: not a key",
+                    "  # still text",
+                    "other: &a 1 # c",
+                    "- *a"
+                ]
+            ),
+            [
+                "kkk..\"",
+                "\"\"\"\"\"\"\"\"\"\"\"\"\"\"\"\"\"",
+                "\"\"\"\"\"\"\"\"\"\"\"\"\"\"",
+                "kkkkk..&&.0.///",
+                "-.&&",
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_json_keys_and_values() {
+        assert_eq!(
+            highlight(
+                include_str!("../syntax/json.toml"),
+                &[
+                    "{",
+                    "  \"key\": [1, -2.5e3, true, null, \"s\\\"x\"],",
+                    "  \"o\": {\"n\": false}"
+                ]
+            ),
+            [
+                ".",
+                "..kkkkk...0...00000..pppp..pppp..\"\"\"\"\"\"..",
+                "..kkk...kkk..ppppp.",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_empty_tokens() {
+        let mut syntax = Syntax::default();
+        syntax.list_markers = vec![String::new()];
+        syntax.emphasis = vec![String::new()];
+        syntax.sections = vec![(String::new(), String::new())];
+        let syntax: &'static Syntax = Box::leak(Box::new(syntax));
+        let mut row = Row::from(" a b");
+        row.highlight(&HighlightOptions::from(syntax), &[], None);
+        assert_eq!(row.highlighting.len(), 4);
+    }
+}
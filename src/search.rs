@@ -0,0 +1,316 @@
+use crate::row::is_separator;
+
+use regex::{Regex, RegexBuilder};
+use termion::event::Key;
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Case {
+    #[default]
+    Sensitive,
+    Insensitive,
+    /// Insensitive unless the text to find has an uppercase letter.
+    Smart,
+}
+
+/// The search options toggled in the search and replace prompts. They are
+/// kept from one search to the next.
+#[derive(Clone, Copy, Default)]
+pub struct Options {
+    pub regex: bool,
+    pub case: Case,
+    pub whole_word: bool,
+    pub wrap: bool,
+    /// Whether the matches stay highlighted after the search ends, until
+    /// they are cleared.
+    pub keep_highlights: bool,
+}
+
+impl Options {
+    /// Changes the option toggled by `key`, returning false if it toggles
+    /// none: Ctrl-r for regex mode, Alt-c to cycle through case sensitive,
+    /// insensitive and smart case, Alt-w for whole words, Alt-a for wrapping
+    /// around the ends of the document and Alt-h for keeping the matches
+    /// highlighted.
+    pub fn toggle(&mut self, key: Key) -> bool {
+        match key {
+            Key::Ctrl('r') => self.regex = !self.regex,
+            Key::Alt('c') => {
+                self.case = match self.case {
+                    Case::Sensitive => Case::Insensitive,
+                    Case::Insensitive => Case::Smart,
+                    Case::Smart => Case::Sensitive,
+                }
+            }
+            Key::Alt('w') => self.whole_word = !self.whole_word,
+            Key::Alt('a') => self.wrap = !self.wrap,
+            Key::Alt('h') => self.keep_highlights = !self.keep_highlights,
+            _ => return false,
+        }
+        true
+    }
+
+    /// The options that are on, as shown in the prompts.
+    pub fn describe(self) -> String {
+        let case = match self.case {
+            Case::Sensitive => None,
+            Case::Insensitive => Some("ignore case"),
+            Case::Smart => Some("smart case"),
+        };
+        [
+            self.regex.then_some("regex"),
+            case,
+            self.whole_word.then_some("word"),
+            self.wrap.then_some("wrap"),
+            self.keep_highlights.then_some("keep"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+/// What the search prompt looks for: its text taken literally, or as a
+/// regular expression when regex mode is on.
+#[derive(Clone)]
+pub struct Query {
+    regex: Regex,
+    is_empty: bool,
+    is_regex: bool,
+    case_insensitive: bool,
+    whole_word: bool,
+}
+
+impl Query {
+    /// Compiles `text` with `options`, describing why if it is not a valid
+    /// regular expression.
+    pub fn new(text: &str, options: Options) -> Result<Self, String> {
+        let pattern = if options.regex {
+            text.to_string()
+        } else {
+            regex::escape(text)
+        };
+        let case_insensitive = match options.case {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => !has_uppercase(text, options.regex),
+        };
+        match RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .multi_line(true)
+            .build()
+        {
+            Ok(regex) => Ok(Self {
+                regex,
+                is_empty: text.is_empty(),
+                is_regex: options.regex,
+                case_insensitive,
+                whole_word: options.whole_word,
+            }),
+            // The last line of a syntax error explains it; the ones before
+            // repeat the pattern and point into it.
+            Err(error) => Err(error
+                .to_string()
+                .lines()
+                .last()
+                .unwrap_or_default()
+                .trim_start_matches("error: ")
+                .to_string()),
+        }
+    }
+
+    /// The replacement for the first match in `text` at or after byte
+    /// `start`. In regex mode, `$1` or `${name}` in `template` stand for the
+    /// text of a capture group and `$$` for a dollar sign; otherwise the
+    /// template is used as it is.
+    pub fn expand(&self, text: &str, start: usize, template: &str) -> String {
+        let mut replacement = String::new();
+        match self.regex.captures_at(text, start) {
+            Some(captures) if self.is_regex => captures.expand(template, &mut replacement),
+            _ => replacement.push_str(template),
+        }
+        replacement
+    }
+
+    /// The byte range of the first match in `text` at or after byte `start`.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        if self.is_empty {
+            return None;
+        }
+        let mut from = start;
+        while let Some(found) = self.regex.find_at(text, from) {
+            if self.is_whole_word(text, found.start(), found.end()) {
+                return Some((found.start(), found.end()));
+            }
+            from = found.start() + text[found.start()..].chars().next()?.len_utf8();
+        }
+        None
+    }
+
+    /// The byte ranges of the matches in `text` that don't overlap.
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        // None once an empty match at the end of `text` was found.
+        let mut from = Some(0);
+        std::iter::from_fn(move || {
+            let found = self.find_at(text, from?)?;
+            from = if found.1 > found.0 {
+                Some(found.1)
+            } else {
+                text[found.1..]
+                    .chars()
+                    .next()
+                    .map(|c| found.1 + c.len_utf8())
+            };
+            Some(found)
+        })
+    }
+
+    /// Whether the match from byte `start` to `end` of `text` is a whole
+    /// word, if only whole words should match.
+    fn is_whole_word(&self, text: &str, start: usize, end: usize) -> bool {
+        !self.whole_word
+            || (text[..start].chars().next_back().is_none_or(is_separator)
+                && text[end..].chars().next().is_none_or(is_separator))
+    }
+}
+
+impl PartialEq for Query {
+    /// Queries are equal when they find the same matches.
+    fn eq(&self, other: &Self) -> bool {
+        self.regex.as_str() == other.regex.as_str()
+            && self.is_regex == other.is_regex
+            && self.case_insensitive == other.case_insensitive
+            && self.whole_word == other.whole_word
+    }
+}
+
+/// Whether `text` has an uppercase letter to find. In a regex, escapes like
+/// `\W` or `\p{Lu}` name classes of characters rather than letters.
+fn has_uppercase(text: &str, regex: bool) -> bool {
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            if matches!(chars.next(), Some('p' | 'P')) && chars.next() == Some('{') {
+                chars.find(|&c| c == '}');
+            }
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(text: &str, options: Options) -> Query {
+        Query::new(text, options).unwrap()
+    }
+
+    fn regex() -> Options {
+        Options {
+            regex: true,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn finds_literal_text_or_regexes() {
+        let literal = query("a.c", Options::default());
+        assert_eq!(literal.find_at("abc a.c", 0), Some((4, 7)));
+        let regex = query("a.c", regex());
+        assert_eq!(regex.find_at("abc a.c", 0), Some((0, 3)));
+        assert_eq!(regex.find_at("abc a.c", 1), Some((4, 7)));
+        assert_eq!(query("", Options::default()).find_at("abc", 0), None);
+    }
+
+    #[test]
+    fn follows_the_case_option() {
+        let options = |case| Options {
+            case,
+            ..Options::default()
+        };
+        assert_eq!(
+            query("foo", options(Case::Sensitive)).find_at("FOO", 0),
+            None
+        );
+        assert!(query("foo", options(Case::Insensitive))
+            .find_at("FOO", 0)
+            .is_some());
+        assert!(query("foo", options(Case::Smart))
+            .find_at("FOO", 0)
+            .is_some());
+        assert_eq!(query("Foo", options(Case::Smart)).find_at("FOO", 0), None);
+    }
+
+    #[test]
+    fn ignores_regex_escapes_for_smart_case() {
+        let options = Options {
+            case: Case::Smart,
+            ..regex()
+        };
+        assert_eq!(query(r"\Wfoo", options).find_at(" FOO", 0), Some((0, 4)));
+        assert!(query(r"\p{Lu}x", options).find_at("AX", 0).is_some());
+        assert_eq!(query(r"\WFoo", options).find_at(" FOO", 0), None);
+        let literal = Options {
+            case: Case::Smart,
+            ..Options::default()
+        };
+        assert_eq!(query(r"\Wfoo", literal).find_at(r"\wfoo", 0), None);
+    }
+
+    #[test]
+    fn matches_whole_words() {
+        let options = Options {
+            whole_word: true,
+            ..Options::default()
+        };
+        let query = query("cat", options);
+        assert_eq!(query.find_at("concat cats cat.", 0), Some((12, 15)));
+        assert_eq!(
+            query.find_iter("cat cats cat").collect::<Vec<_>>(),
+            [(0, 3), (9, 12)]
+        );
+    }
+
+    #[test]
+    fn steps_past_empty_matches() {
+        let query = query("x*", regex());
+        assert_eq!(
+            query.find_iter("axxé").collect::<Vec<_>>(),
+            [(0, 0), (1, 3), (3, 3), (5, 5)]
+        );
+    }
+
+    #[test]
+    fn expands_capture_groups_in_regex_mode() {
+        let text = "key = value";
+        let template = "$2: ${1}$$";
+        assert_eq!(
+            query(r"(\w+) = (\w+)", regex()).expand(text, 0, template),
+            "value: key$"
+        );
+        assert_eq!(
+            query("key", Options::default()).expand(text, 0, template),
+            template
+        );
+    }
+
+    #[test]
+    fn describes_invalid_regexes() {
+        let error = Query::new("(", regex()).err().unwrap();
+        assert_eq!(error, "unclosed group");
+    }
+
+    #[test]
+    fn compares_what_queries_find() {
+        assert!(query("a", Options::default()) == query("a", Options::default()));
+        assert!(query("a", Options::default()) != query("a", regex()));
+        let insensitive = Options {
+            case: Case::Insensitive,
+            ..Options::default()
+        };
+        assert!(query("a", Options::default()) != query("a", insensitive));
+    }
+}
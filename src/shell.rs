@@ -0,0 +1,82 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Runs `command` through `sh -c`, feeding `input` on stdin, and returns its
+/// stdout and the first line of its stderr. A failure to spawn or a non-zero
+/// exit is reported as a message suitable for the message bar, including the
+/// first line of stderr.
+pub fn filter(command: &str, input: &str) -> Result<(String, String), String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("ERR: could not run `{}`: {}", command, err))?;
+
+    // Write from another thread so a command that produces output before
+    // consuming all of its input can't deadlock against us.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("ERR: could not run `{}`: {}", command, err))?;
+    // A command that ignores its input closes the pipe early; that is not an
+    // error worth reporting.
+    let _ = writer.join();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = stderr.lines().next().unwrap_or("").trim();
+    if !output.status.success() {
+        let status = output
+            .status
+            .code()
+            .map_or_else(|| "signal".to_string(), |code| code.to_string());
+        return Err(format!(
+            "ERR: `{}` exited with {}: {}",
+            command, status, stderr
+        ));
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .map_err(|_| format!("ERR: `{}` produced invalid UTF-8", command))?;
+    Ok((stdout, stderr.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipes_input_through_the_command() {
+        assert_eq!(
+            filter("sort", "b\na\n"),
+            Ok(("a\nb\n".to_string(), String::new()))
+        );
+        assert_eq!(
+            filter("echo out; echo warning >&2", ""),
+            Ok(("out\n".to_string(), "warning".to_string()))
+        );
+    }
+
+    #[test]
+    fn tolerates_commands_that_ignore_their_input() {
+        let input = "x".repeat(1 << 20);
+        assert_eq!(filter("true", &input), Ok((String::new(), String::new())));
+    }
+
+    #[test]
+    fn reports_failures() {
+        assert_eq!(
+            filter("echo bad >&2; echo more >&2; exit 3", "input"),
+            Err("ERR: `echo bad >&2; echo more >&2; exit 3` exited with 3: bad".to_string())
+        );
+        assert_eq!(
+            filter("printf '\\377'", ""),
+            Err("ERR: `printf '\\377'` produced invalid UTF-8".to_string())
+        );
+    }
+}
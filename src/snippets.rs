@@ -0,0 +1,288 @@
+use crate::paths;
+use crate::Position;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use unicode_segmentation::UnicodeSegmentation;
+
+const SNIPPETS_DIR: &str = "snippets";
+
+/// Snippets that ship with chewol, by file type. A snippet file in the config
+/// directory adds to these and overrides those with the same trigger.
+const DEFAULT_SNIPPETS: &[(&str, &str)] = &[("rust", include_str!("../snippets/rust.snippets"))];
+
+/// Snippet bodies by trigger word for each file type, loaded the first time a
+/// buffer of that type asks for one.
+#[derive(Default)]
+pub struct Snippets {
+    by_file_type: HashMap<String, HashMap<String, String>>,
+}
+
+impl Snippets {
+    pub fn get(&mut self, file_type: &str, trigger: &str) -> Option<&str> {
+        let id = file_type.to_lowercase().replace(' ', "-");
+        self.by_file_type
+            .entry(id)
+            .or_insert_with_key(|id| load(id))
+            .get(trigger)
+            .map(String::as_str)
+    }
+}
+
+/// The text a snippet expands to and where its tab stops ended up.
+pub struct Expansion {
+    pub text: String,
+    /// The ranges of each tab stop in the order they are visited, `$0` last.
+    /// The first range of a stop is the one typed into; the others mirror it.
+    pub stops: Vec<Vec<(Position, Position)>>,
+}
+
+enum Token {
+    Text(String),
+    Stop(usize, Option<String>),
+}
+
+/// Expands `body` for insertion at `at`, indenting every line after the first
+/// with `indent`. `$1` marks a tab stop, `${1:text}` gives it a placeholder,
+/// later occurrences of the same number mirror it, and `$0` is where the
+/// cursor ends up, after the text if the body doesn't say.
+pub fn expand(body: &str, at: &Position, indent: &str) -> Expansion {
+    let tokens = tokenize(body);
+    let mut placeholders = HashMap::new();
+    for token in &tokens {
+        if let Token::Stop(number, Some(placeholder)) = token {
+            placeholders
+                .entry(*number)
+                .or_insert_with(|| placeholder.clone());
+        }
+    }
+
+    let mut text = String::new();
+    let mut end = at.clone();
+    let mut ranges: BTreeMap<usize, Vec<(Position, Position)>> = BTreeMap::new();
+    let push = |text: &mut String, end: &mut Position, fragment: &str| {
+        for (index, line) in fragment.split('\n').enumerate() {
+            if index > 0 {
+                text.push('\n');
+                text.push_str(indent);
+                end.y = end.y.saturating_add(1);
+                end.x = indent.graphemes(true).count();
+            }
+            text.push_str(line);
+            end.x = end.x.saturating_add(line.graphemes(true).count());
+        }
+    };
+    for token in &tokens {
+        match token {
+            Token::Text(fragment) => push(&mut text, &mut end, fragment),
+            Token::Stop(number, _) => {
+                let start = end.clone();
+                let placeholder = placeholders.get(number).map_or("", String::as_str);
+                push(&mut text, &mut end, placeholder);
+                ranges
+                    .entry(*number)
+                    .or_default()
+                    .push((start, end.clone()));
+            }
+        }
+    }
+
+    let last = ranges
+        .remove(&0)
+        .unwrap_or_else(|| vec![(end.clone(), end)]);
+    let mut stops: Vec<_> = ranges.into_values().collect();
+    stops.push(last);
+    Expansion { text, stops }
+}
+
+/// Parses a snippet file: each snippet starts with a `snippet <trigger>` line
+/// and its body is the following lines, each indented by one tab. Lines
+/// starting with `#` outside a body are comments.
+pub fn parse(contents: &str) -> HashMap<String, String> {
+    let mut snippets = HashMap::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+    let mut finish = |current: Option<(String, Vec<&str>)>| {
+        if let Some((trigger, mut lines)) = current {
+            while lines.last().is_some_and(|line| line.is_empty()) {
+                lines.pop();
+            }
+            snippets.insert(trigger, lines.join("\n"));
+        }
+    };
+    for line in contents.lines() {
+        if let Some(trigger) = line.strip_prefix("snippet ") {
+            finish(current.take());
+            current = Some((trigger.trim().to_string(), Vec::new()));
+        } else if let Some((_, lines)) = &mut current {
+            if let Some(body) = line.strip_prefix('\t') {
+                lines.push(body);
+            } else if line.is_empty() {
+                lines.push(line);
+            } else {
+                finish(current.take());
+            }
+        }
+    }
+    finish(current);
+    snippets
+}
+
+fn load(id: &str) -> HashMap<String, String> {
+    let mut snippets = DEFAULT_SNIPPETS
+        .iter()
+        .find(|(file_type, _)| *file_type == id)
+        .map(|(_, contents)| parse(contents))
+        .unwrap_or_default();
+    let contents = paths::config_dir().and_then(|dir| {
+        fs::read_to_string(dir.join(SNIPPETS_DIR).join(format!("{}.snippets", id))).ok()
+    });
+    snippets.extend(parse(&contents.unwrap_or_default()));
+    snippets
+}
+
+/// Splits a snippet body into text and tab stops. A backslash escapes `$`,
+/// `}` and itself; a `$` that doesn't start a tab stop is kept as is.
+fn tokenize(body: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars
+                .peek()
+                .is_some_and(|next| matches!(next, '$' | '}' | '\\')) =>
+            {
+                text.extend(chars.next());
+            }
+            '$' if chars.peek().is_some_and(char::is_ascii_digit) => {
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    number.push(digit);
+                }
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+                tokens.push(Token::Stop(number.parse().unwrap_or(0), None));
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                let rest: String = chars.clone().skip(1).collect();
+                let digits = rest.chars().take_while(char::is_ascii_digit).count();
+                let after = rest.chars().nth(digits);
+                if digits == 0 || !matches!(after, Some(':' | '}')) {
+                    text.push(c);
+                    continue;
+                }
+                let number = rest[..digits].parse().unwrap_or(0);
+                chars.nth(digits + 1);
+                let placeholder = if after == Some(':') {
+                    Some(placeholder(&mut chars))
+                } else {
+                    None
+                };
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+                tokens.push(Token::Stop(number, placeholder));
+            }
+            _ => text.push(c),
+        }
+    }
+    tokens.push(Token::Text(text));
+    tokens
+}
+
+/// Reads a placeholder up to the `}` that closes it, allowing balanced braces
+/// inside it.
+fn placeholder(chars: &mut impl Iterator<Item = char>) -> String {
+    let mut placeholder = String::new();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => placeholder.extend(chars.next()),
+            '{' => depth += 1,
+            '}' if depth == 0 => break,
+            '}' => depth -= 1,
+            _ => (),
+        }
+        if c != '\\' {
+            placeholder.push(c);
+        }
+    }
+    placeholder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `(x, y)` of the start and end of each range of a tab stop.
+    type Ranges = Vec<((usize, usize), (usize, usize))>;
+
+    fn stops(expansion: &Expansion) -> Vec<Ranges> {
+        expansion
+            .stops
+            .iter()
+            .map(|ranges| {
+                ranges
+                    .iter()
+                    .map(|(start, end)| ((start.x, start.y), (end.x, end.y)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenizes_stops_and_escapes() {
+        let tokens = tokenize(r"a \$1 $2 ${3:{x} y\}} $ ${z}");
+        let described: Vec<String> = tokens
+            .iter()
+            .map(|token| match token {
+                Token::Text(text) => format!("text {}", text),
+                Token::Stop(number, None) => format!("stop {}", number),
+                Token::Stop(number, Some(placeholder)) => {
+                    format!("stop {} {}", number, placeholder)
+                }
+            })
+            .collect();
+        assert_eq!(
+            described,
+            [
+                "text a $1 ",
+                "stop 2",
+                "text  ",
+                "stop 3 {x} y}",
+                "text  $ ${z}",
+            ]
+        );
+    }
+
+    #[test]
+    fn expands_placeholders_and_mirrors() {
+        let at = Position { x: 4, y: 2 };
+        let expansion = expand("fn ${1:name}() -> $2 {\n    $1$0\n}", &at, "  ");
+        assert_eq!(expansion.text, "fn name() ->  {\n      name\n  }");
+        assert_eq!(
+            stops(&expansion),
+            [
+                vec![((7, 2), (11, 2)), ((6, 3), (10, 3))],
+                vec![((17, 2), (17, 2))],
+                vec![((10, 3), (10, 3))],
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_after_the_text_without_a_final_stop() {
+        let expansion = expand("${1:a}b", &Position::default(), "");
+        assert_eq!(expansion.text, "ab");
+        assert_eq!(
+            stops(&expansion),
+            [vec![((0, 0), (1, 0))], vec![((2, 0), (2, 0))]]
+        );
+    }
+
+    #[test]
+    fn parses_snippet_files() {
+        let snippets =
+            parse("# comment\nsnippet one\n\tfirst\n\n\t\tsecond\n\nsnippet two\n\tx\nafter\n");
+        assert_eq!(snippets.len(), 2);
+        assert_eq!(snippets["one"], "first\n\n\tsecond");
+        assert_eq!(snippets["two"], "x");
+    }
+}
@@ -0,0 +1,430 @@
+use crate::editorconfig;
+use crate::paths;
+
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+const SYNTAX_DIR: &str = "syntax";
+
+/// Syntax definitions that ship with chewol. A definition in the config
+/// directory with the same name replaces one of these.
+const DEFAULT_SYNTAXES: &[(&str, &str)] = &[
+    ("c.toml", include_str!("../syntax/c.toml")),
+    ("cpp.toml", include_str!("../syntax/cpp.toml")),
+    ("json.toml", include_str!("../syntax/json.toml")),
+    ("markdown.toml", include_str!("../syntax/markdown.toml")),
+    ("python.toml", include_str!("../syntax/python.toml")),
+    ("rust.toml", include_str!("../syntax/rust.toml")),
+    ("toml.toml", include_str!("../syntax/toml.toml")),
+    ("yaml.toml", include_str!("../syntax/yaml.toml")),
+];
+
+/// A delimiter that starts and ends a string, the character that escapes
+/// it inside the string, if any, and whether the string can span lines.
+pub struct StringRule {
+    pub delimiter: String,
+    pub escape: Option<char>,
+    pub multiline: bool,
+}
+
+/// How numbers continue after their first digit.
+#[derive(Default)]
+pub struct NumberRules {
+    /// Characters other than digits that can continue a number, like `.`.
+    pub continuation: String,
+    /// Prefixes, like `0x`, after which any letters and digits belong to
+    /// the number.
+    pub prefixes: Vec<String>,
+    /// Characters that can end a number, like the `u` and `l` of `10ul`.
+    pub suffixes: String,
+}
+
+/// A syntax definition: which files it applies to and how to highlight them.
+#[derive(Default)]
+pub struct Syntax {
+    pub name: String,
+    extensions: Vec<String>,
+    globs: Vec<String>,
+    /// Tokens that start a line comment, longest first so that `///` is
+    /// recognized before `//`.
+    pub line_comments: Vec<String>,
+    pub block_comments: Vec<(String, String)>,
+    pub strings: Vec<StringRule>,
+    /// Letters that can come right before a string delimiter, like the `f`
+    /// of a Python f-string.
+    pub string_prefixes: Vec<String>,
+    /// The character that starts a decorator at the beginning of a line.
+    pub decorators: Option<char>,
+    /// The character that starts a preprocessor directive at the beginning
+    /// of a line.
+    pub preprocessor: Option<char>,
+    /// The quote around single-character literals.
+    pub characters: Option<char>,
+    /// The character that starts a heading, repeated for its level.
+    pub headings: Option<char>,
+    /// The character that starts a block quote.
+    pub block_quotes: Option<char>,
+    /// Markers that start a list item when followed by whitespace.
+    pub list_markers: Vec<String>,
+    /// Whether a number followed by `.` or `)` starts a list item.
+    pub ordered_lists: bool,
+    /// Delimiters around emphasis, doubled for strong emphasis.
+    pub emphasis: Vec<String>,
+    /// The character whose runs delimit inline code.
+    pub code_spans: Option<char>,
+    /// The fences that start and end a block of code on lines of their own.
+    pub code_blocks: Vec<(String, String)>,
+    /// Whether `[text](target)` is a link.
+    pub links: bool,
+    /// Tokens that follow a key, like the `=` of `key = value`.
+    pub key_separators: Vec<String>,
+    /// The tokens around a section header on a line of its own.
+    pub sections: Vec<(String, String)>,
+    /// Characters that start an anchor or alias, like YAML's `&` and `*`.
+    pub anchors: String,
+    /// Characters that start a block scalar after a key separator, like
+    /// YAML's `|` and `>`.
+    pub block_scalars: String,
+    pub numbers: Option<NumberRules>,
+    pub primary_keywords: Vec<String>,
+    pub secondary_keywords: Vec<String>,
+}
+
+impl Syntax {
+    /// Whether the definition applies to `filename`, by its extension or a
+    /// glob matching its name, or its path if the glob has a `/`.
+    fn applies_to(&self, filename: &str) -> bool {
+        let path = Path::new(filename);
+        let extension = path.extension().map(|ext| ext.to_string_lossy());
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        self.extensions.iter().any(|ext| {
+            extension
+                .as_ref()
+                .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+        }) || self.globs.iter().any(|glob| {
+            if glob.contains('/') {
+                editorconfig::matches(glob, filename)
+            } else {
+                editorconfig::matches(glob, &name)
+            }
+        })
+    }
+}
+
+struct Loaded {
+    syntaxes: Vec<Syntax>,
+    errors: Vec<String>,
+}
+
+/// The definition for `filename`, if any applies to it.
+pub fn find(filename: &str) -> Option<&'static Syntax> {
+    loaded()
+        .syntaxes
+        .iter()
+        .find(|syntax| syntax.applies_to(filename))
+}
+
+/// The definition of files no other definition applies to, which
+/// highlights nothing.
+pub fn plain() -> &'static Syntax {
+    static PLAIN: OnceLock<Syntax> = OnceLock::new();
+    PLAIN.get_or_init(Syntax::default)
+}
+
+/// What was wrong with the definitions that could not be loaded.
+pub fn errors() -> &'static [String] {
+    &loaded().errors
+}
+
+fn loaded() -> &'static Loaded {
+    static LOADED: OnceLock<Loaded> = OnceLock::new();
+    LOADED.get_or_init(load)
+}
+
+/// Loads the `.toml` definitions in the config directory, then the default
+/// ones they don't replace.
+fn load() -> Loaded {
+    let mut files = Vec::new();
+    if let Some(entries) =
+        paths::config_dir().and_then(|dir| fs::read_dir(dir.join(SYNTAX_DIR)).ok())
+    {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "toml") {
+                let name = entry.file_name().to_string_lossy().to_string();
+                files.push((name, fs::read_to_string(path)));
+            }
+        }
+    }
+    files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut loaded = Loaded {
+        syntaxes: Vec::new(),
+        errors: Vec::new(),
+    };
+    for (name, contents) in files {
+        match contents
+            .map_err(|error| error.to_string())
+            .and_then(|c| parse(&c))
+        {
+            Ok(syntax) => loaded.syntaxes.push(syntax),
+            Err(error) => loaded.errors.push(format!("syntax {}: {}", name, error)),
+        }
+    }
+    for (name, contents) in DEFAULT_SYNTAXES {
+        match parse(contents) {
+            Ok(syntax) => {
+                if !loaded
+                    .syntaxes
+                    .iter()
+                    .any(|other| other.name.eq_ignore_ascii_case(&syntax.name))
+                {
+                    loaded.syntaxes.push(syntax);
+                }
+            }
+            Err(error) => loaded.errors.push(format!("syntax {}: {}", name, error)),
+        }
+    }
+    loaded
+}
+
+/// Parses a syntax definition, describing the first problem with it if it
+/// is not valid TOML or not a valid definition.
+pub fn parse(contents: &str) -> Result<Syntax, String> {
+    let table: Table = contents
+        .parse()
+        .map_err(|error: toml::de::Error| match error.span() {
+            Some(span) => {
+                let line = contents[..span.start].matches('\n').count() + 1;
+                format!("line {}: {}", line, error.message().trim())
+            }
+            None => error.message().trim().to_string(),
+        })?;
+    let mut syntax = Syntax::default();
+    for (key, value) in &table {
+        match key.as_str() {
+            "name" => syntax.name = string(key, value)?,
+            "extensions" => syntax.extensions = strings(key, value)?,
+            "globs" => syntax.globs = strings(key, value)?,
+            "line_comments" => syntax.line_comments = tokens(key, value)?,
+            "block_comments" => syntax.block_comments = pairs(key, value)?,
+            "strings" => {
+                syntax.strings = array(key, value)?
+                    .iter()
+                    .map(|rule| string_rule(key, rule))
+                    .collect::<Result<_, _>>()?;
+            }
+            "string_prefixes" => syntax.string_prefixes = tokens(key, value)?,
+            "decorators" => syntax.decorators = Some(character(key, value)?),
+            "preprocessor" => syntax.preprocessor = Some(character(key, value)?),
+            "characters" => syntax.characters = Some(character(key, value)?),
+            "headings" => syntax.headings = Some(character(key, value)?),
+            "block_quotes" => syntax.block_quotes = Some(character(key, value)?),
+            "list_markers" => syntax.list_markers = tokens(key, value)?,
+            "ordered_lists" => syntax.ordered_lists = boolean(key, value)?,
+            "emphasis" => syntax.emphasis = tokens(key, value)?,
+            "code_spans" => syntax.code_spans = Some(character(key, value)?),
+            "code_blocks" => syntax.code_blocks = pairs(key, value)?,
+            "links" => syntax.links = boolean(key, value)?,
+            "key_separators" => syntax.key_separators = tokens(key, value)?,
+            "sections" => syntax.sections = pairs(key, value)?,
+            "anchors" => syntax.anchors = string(key, value)?,
+            "block_scalars" => syntax.block_scalars = string(key, value)?,
+            "numbers" => syntax.numbers = Some(number_rules(key, value)?),
+            "primary_keywords" => syntax.primary_keywords = strings(key, value)?,
+            "secondary_keywords" => syntax.secondary_keywords = strings(key, value)?,
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+    }
+    if syntax.name.is_empty() {
+        return Err("missing `name`".to_string());
+    }
+    syntax
+        .line_comments
+        .sort_by_key(|token| std::cmp::Reverse(token.len()));
+    Ok(syntax)
+}
+
+fn string(key: &str, value: &Value) -> Result<String, String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("`{}` must be a string", key))
+}
+
+fn boolean(key: &str, value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("`{}` must be a boolean", key))
+}
+
+fn character(key: &str, value: &Value) -> Result<char, String> {
+    let text = string(key, value)?;
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("`{}` must be a single character", key)),
+    }
+}
+
+fn array<'a>(key: &str, value: &'a Value) -> Result<&'a Vec<Value>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("`{}` must be an array", key))
+}
+
+fn strings(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    array(key, value)?
+        .iter()
+        .map(|item| {
+            item.as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("`{}` must be an array of strings", key))
+        })
+        .collect()
+}
+
+fn tokens(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    let tokens = strings(key, value)?;
+    if tokens.iter().any(String::is_empty) {
+        return Err(format!("`{}` must not contain empty strings", key));
+    }
+    Ok(tokens)
+}
+
+fn pairs(key: &str, value: &Value) -> Result<Vec<(String, String)>, String> {
+    array(key, value)?
+        .iter()
+        .map(|pair| match tokens(key, pair)?.as_slice() {
+            [start, end] => Ok((start.clone(), end.clone())),
+            _ => Err(format!("`{}` must hold pairs of strings", key)),
+        })
+        .collect()
+}
+
+fn table<'a>(key: &str, value: &'a Value) -> Result<&'a Table, String> {
+    value
+        .as_table()
+        .ok_or_else(|| format!("`{}` must be a table", key))
+}
+
+fn string_rule(key: &str, value: &Value) -> Result<StringRule, String> {
+    let mut rule = StringRule {
+        delimiter: String::new(),
+        escape: None,
+        multiline: false,
+    };
+    for (field, value) in table(key, value)? {
+        match field.as_str() {
+            "delimiter" => rule.delimiter = string(&format!("{}.delimiter", key), value)?,
+            "escape" => rule.escape = Some(character(&format!("{}.escape", key), value)?),
+            "multiline" => rule.multiline = boolean(&format!("{}.multiline", key), value)?,
+            _ => return Err(format!("unknown key `{}.{}`", key, field)),
+        }
+    }
+    if rule.delimiter.is_empty() {
+        return Err(format!("`{}` entries need a `delimiter`", key));
+    }
+    Ok(rule)
+}
+
+fn number_rules(key: &str, value: &Value) -> Result<NumberRules, String> {
+    let mut rules = NumberRules::default();
+    for (field, value) in table(key, value)? {
+        match field.as_str() {
+            "continuation" => rules.continuation = string(&format!("{}.continuation", key), value)?,
+            "prefixes" => rules.prefixes = strings(&format!("{}.prefixes", key), value)?,
+            "suffixes" => rules.suffixes = string(&format!("{}.suffixes", key), value)?,
+            _ => return Err(format!("unknown key `{}.{}`", key, field)),
+        }
+    }
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(contents: &str) -> String {
+        parse(contents).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn parses_the_default_definitions() {
+        for (name, contents) in DEFAULT_SYNTAXES {
+            assert!(parse(contents).is_ok(), "{}", name);
+        }
+        let rust = parse(include_str!("../syntax/rust.toml")).unwrap();
+        let numbers = rust.numbers.unwrap();
+        assert_eq!(numbers.continuation, "._");
+        assert_eq!(numbers.prefixes, ["0x", "0o", "0b"]);
+    }
+
+    #[test]
+    fn sorts_line_comments_longest_first() {
+        let syntax = parse("name = \"x\"\nline_comments = [\"#\", \"###\", \"##\"]").unwrap();
+        assert_eq!(syntax.line_comments, ["###", "##", "#"]);
+    }
+
+    #[test]
+    fn describes_invalid_definitions() {
+        assert_eq!(error("name = \"x\"\nbogus = 1"), "unknown key `bogus`");
+        assert_eq!(
+            error("name = \"x\"\n[numbers]\nsuffix = \"u\""),
+            "unknown key `numbers.suffix`"
+        );
+        assert_eq!(error("extensions = [\"x\"]"), "missing `name`");
+        assert_eq!(
+            error("name = \"x\"\ncharacters = \"''\""),
+            "`characters` must be a single character"
+        );
+        assert_eq!(
+            error("name = \"x\"\nblock_comments = [[\"/*\"]]"),
+            "`block_comments` must hold pairs of strings"
+        );
+        assert_eq!(
+            error("name = \"x\"\nstrings = [{ escape = \"\\\\\" }]"),
+            "`strings` entries need a `delimiter`"
+        );
+        assert!(error("name = \"x\"\n\nlinks = yes").starts_with("line 3: "));
+    }
+
+    #[test]
+    fn rejects_empty_tokens() {
+        for key in [
+            "line_comments",
+            "string_prefixes",
+            "list_markers",
+            "emphasis",
+            "key_separators",
+        ] {
+            assert_eq!(
+                error(&format!("name = \"x\"\n{} = [\"a\", \"\"]", key)),
+                format!("`{}` must not contain empty strings", key)
+            );
+        }
+        for key in ["block_comments", "code_blocks", "sections"] {
+            assert_eq!(
+                error(&format!("name = \"x\"\n{} = [[\"a\", \"\"]]", key)),
+                format!("`{}` must not contain empty strings", key)
+            );
+        }
+    }
+
+    #[test]
+    fn applies_by_extension_or_glob() {
+        let syntax =
+            parse("name = \"x\"\nextensions = [\"md\"]\nglobs = [\"Makefile\", \"docs/*.txt\"]")
+                .unwrap();
+        assert!(syntax.applies_to("notes.MD"));
+        assert!(syntax.applies_to("src/Makefile"));
+        assert!(syntax.applies_to("docs/a.txt"));
+        assert!(!syntax.applies_to("a.txt"));
+        assert!(!syntax.applies_to("md"));
+    }
+}
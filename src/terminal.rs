@@ -1,35 +1,71 @@
 use crate::Position;
 
 use std::io::{self, stdout, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 use termion::{
     clear, color, cursor,
-    event::Key,
+    event::{self, Key},
     input::TermRead,
     raw::{IntoRawMode, RawTerminal},
 };
 
+/// Asks the terminal to report when it gains and loses focus.
+const ENABLE_FOCUS_EVENTS: &str = "\x1b[?1004h";
+const DISABLE_FOCUS_EVENTS: &str = "\x1b[?1004l";
+const FOCUS_OUT: &[u8] = b"\x1b[O";
+
 pub struct Size {
     pub width: u16,
     pub height: u16,
 }
 
+pub enum Event {
+    Key(Key),
+    FocusOut,
+}
+
 pub struct Terminal {
     size: Size,
     _stdout: RawTerminal<std::io::Stdout>,
+    /// Input parsed by a thread that owns stdin, so that reading can time
+    /// out and bytes read ahead for one event are kept for the next.
+    events: Receiver<Result<event::Event, io::Error>>,
+    /// Whether the terminal was asked to report focus changes, so that it can
+    /// be told to stop on exit.
+    focus_events: bool,
 }
 
 impl Terminal {
     pub fn default() -> Result<Self, std::io::Error> {
         let size = termion::terminal_size()?;
+        let (sender, events) = mpsc::channel();
+        thread::spawn(move || {
+            for event in io::stdin().events() {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
         Ok(Self {
             size: Size {
                 width: size.0,
                 height: size.1.saturating_sub(2), // accommodate status bar height
             },
             _stdout: stdout().into_raw_mode()?,
+            events,
+            focus_events: false,
         })
     }
 
+    /// Asks the terminal to report focus changes, which are read as
+    /// `Event::FocusOut` when it loses focus.
+    pub fn enable_focus_events(&mut self) {
+        print!("{}", ENABLE_FOCUS_EVENTS);
+        self.focus_events = true;
+    }
+
     pub fn clear_current_line() {
         print!("{}", clear::CurrentLine);
     }
@@ -60,10 +96,30 @@ impl Terminal {
         std::io::stdout().flush()
     }
 
-    pub fn read_key() -> Result<Key, std::io::Error> {
+    /// Waits for a key press or a loss of focus, returning `None` if there
+    /// was neither within `timeout`. Without a timeout it waits indefinitely.
+    pub fn read_event(&self, timeout: Option<Duration>) -> Result<Option<Event>, std::io::Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
         loop {
-            if let Some(key) = io::stdin().lock().keys().next() {
-                return key;
+            let event = match deadline {
+                Some(deadline) => {
+                    match self
+                        .events
+                        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => return Ok(None),
+                        Err(RecvTimeoutError::Disconnected) => return Err(closed()),
+                    }
+                }
+                None => self.events.recv().map_err(|_| closed())?,
+            };
+            match event? {
+                event::Event::Key(key) => return Ok(Some(Event::Key(key))),
+                event::Event::Unsupported(bytes) if bytes == FOCUS_OUT => {
+                    return Ok(Some(Event::FocusOut));
+                }
+                _ => (),
             }
         }
     }
@@ -88,3 +144,16 @@ impl Terminal {
         &self.size
     }
 }
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.focus_events {
+            print!("{}", DISABLE_FOCUS_EVENTS);
+            let _ = Self::flush();
+        }
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "stdin was closed")
+}
@@ -0,0 +1,213 @@
+use crate::Position;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A single reversible change: `removed` was replaced by `inserted` at `at`.
+#[derive(Clone)]
+pub struct Edit {
+    pub at: Position,
+    pub removed: String,
+    pub inserted: String,
+}
+
+/// Undo and redo history. Each entry is a group of edits, in the order they
+/// were made, that undo and redo treat as one.
+#[derive(Default)]
+pub struct UndoStack {
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    /// Whether edits are being collected into the last group of `undo`.
+    grouping: bool,
+    /// Whether the last group of `undo` is a run of single-grapheme edits,
+    /// which later ones can be merged into.
+    merging: bool,
+}
+
+impl UndoStack {
+    /// Starts collecting the edits that follow into one group.
+    pub fn begin_group(&mut self) {
+        self.undo.push(Vec::new());
+        self.grouping = true;
+        self.merging = false;
+    }
+
+    pub fn end_group(&mut self) {
+        self.grouping = false;
+        if self.undo.last().is_some_and(Vec::is_empty) {
+            self.undo.pop();
+        }
+    }
+
+    pub fn push(&mut self, edit: Edit) {
+        self.redo.clear();
+        if let Some(group) = self.undo.last_mut() {
+            if self.grouping {
+                group.push(edit);
+                return;
+            }
+            if let [last] = group.as_mut_slice() {
+                if self.merging && edit.is_single() && last.merge(&edit) {
+                    return;
+                }
+            }
+        }
+        self.merging = edit.is_single();
+        self.undo.push(vec![edit]);
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Vec<Edit>> {
+        self.merging = false;
+        let group = self.undo.pop()?;
+        self.redo.push(group.clone());
+        Some(group)
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Vec<Edit>> {
+        self.merging = false;
+        let group = self.redo.pop()?;
+        self.undo.push(group.clone());
+        Some(group)
+    }
+}
+
+impl Edit {
+    /// Whether this is a single key's worth of typing or deleting.
+    fn is_single(&self) -> bool {
+        grapheme_count(&self.inserted) + grapheme_count(&self.removed) == 1
+    }
+
+    /// Folds consecutive single-character typing or deleting on one row into
+    /// one edit, so undo steps back a word at a time rather than a key.
+    fn merge(&mut self, next: &Self) -> bool {
+        if self.at.y != next.at.y || next.inserted.contains('\n') || next.removed.contains('\n') {
+            return false;
+        }
+        let typing = self.removed.is_empty() && next.removed.is_empty();
+        let deleting = self.inserted.is_empty() && next.inserted.is_empty();
+        if typing && !self.inserted.ends_with(' ') {
+            let end = self.at.x.saturating_add(grapheme_count(&self.inserted));
+            if next.at.x == end {
+                self.inserted.push_str(&next.inserted);
+                return true;
+            }
+        } else if deleting {
+            if next.at.x == self.at.x {
+                self.removed.push_str(&next.removed);
+                return true;
+            }
+            if next.at.x.saturating_add(grapheme_count(&next.removed)) == self.at.x {
+                self.removed = format!("{}{}", next.removed, self.removed);
+                self.at.x = next.at.x;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn grapheme_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(x: usize, text: &str) -> Edit {
+        Edit {
+            at: Position { x, y: 0 },
+            removed: String::new(),
+            inserted: text.to_string(),
+        }
+    }
+
+    fn deleted(x: usize, text: &str) -> Edit {
+        Edit {
+            at: Position { x, y: 0 },
+            removed: text.to_string(),
+            inserted: String::new(),
+        }
+    }
+
+    /// The groups of `stack`, newest first, with each edit described as
+    /// `+inserted` or `-removed`.
+    fn groups(stack: &mut UndoStack) -> Vec<String> {
+        std::iter::from_fn(|| stack.pop_undo())
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|edit| format!("-{}+{}", edit.removed, edit.inserted))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merges_typing_and_deleting_runs() {
+        let mut stack = UndoStack::default();
+        for (x, c) in "ab c".chars().enumerate() {
+            stack.push(typed(x, &c.to_string()));
+        }
+        stack.push(typed(9, "d"));
+        stack.push(deleted(9, "d"));
+        stack.push(deleted(2, " "));
+        stack.push(deleted(1, "b"));
+        stack.push(deleted(1, "c"));
+        assert_eq!(groups(&mut stack), ["-b c+", "-d+", "-+d", "-+c", "-+ab "]);
+    }
+
+    #[test]
+    fn keeps_pastes_apart_from_typing() {
+        let mut stack = UndoStack::default();
+        stack.push(typed(0, "a"));
+        stack.push(typed(1, "pasted"));
+        stack.push(typed(7, "b"));
+        stack.push(typed(8, "c"));
+        stack.push(deleted(8, "cb"));
+        stack.push(deleted(7, "x"));
+        assert_eq!(
+            groups(&mut stack),
+            ["-x+", "-cb+", "-+bc", "-+pasted", "-+a"]
+        );
+    }
+
+    #[test]
+    fn does_not_merge_into_an_undone_or_redone_group() {
+        let mut stack = UndoStack::default();
+        stack.push(typed(0, "a"));
+        stack.push(typed(5, "b"));
+        stack.pop_undo();
+        stack.push(typed(1, "c"));
+        assert_eq!(groups(&mut stack), ["-+c", "-+a"]);
+        stack.pop_redo();
+        stack.push(typed(1, "d"));
+        assert_eq!(groups(&mut stack), ["-+d", "-+a"]);
+    }
+
+    #[test]
+    fn undoes_and_redoes_a_group_as_one_step() {
+        let mut stack = UndoStack::default();
+        stack.push(typed(0, "a"));
+        stack.begin_group();
+        stack.push(deleted(0, "a"));
+        stack.push(typed(0, "b"));
+        stack.push(typed(1, "c"));
+        stack.end_group();
+        stack.push(typed(2, "d"));
+        assert_eq!(stack.pop_undo().map(|group| group.len()), Some(1));
+        assert_eq!(stack.pop_undo().map(|group| group.len()), Some(3));
+        assert_eq!(stack.pop_redo().map(|group| group.len()), Some(3));
+        stack.push(typed(2, "e"));
+        assert!(stack.pop_redo().is_none());
+        assert_eq!(groups(&mut stack), ["-+e", "-a+ -+b -+c", "-+a"]);
+    }
+
+    #[test]
+    fn drops_empty_groups() {
+        let mut stack = UndoStack::default();
+        stack.begin_group();
+        stack.end_group();
+        assert!(stack.pop_undo().is_none());
+    }
+}
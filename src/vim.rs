@@ -0,0 +1,781 @@
+use crate::Document;
+use crate::Position;
+
+use termion::event::Key;
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    FindForward(char),
+    TillForward(char),
+    FindBackward(char),
+    TillBackward(char),
+}
+
+impl Motion {
+    /// Whether an operator applied with this motion includes the character
+    /// under the target position.
+    pub fn is_inclusive(self) -> bool {
+        matches!(
+            self,
+            Motion::WordEnd | Motion::LineEnd | Motion::FindForward(_) | Motion::TillForward(_)
+        )
+    }
+
+    /// Whether an operator applied with this motion acts on whole lines.
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Command {
+    Move(Motion),
+    Operate(Operator, Motion),
+    /// An operator doubled, as in `dd`, acting on whole lines.
+    OperateLines(Operator),
+    /// An operator applied to the visual selection.
+    OperateSelection(Operator),
+    Insert,
+    InsertAfter,
+    InsertLineStart,
+    InsertLineEnd,
+    OpenBelow,
+    OpenAbove,
+    DeleteChar,
+    DeleteCharBefore,
+    PasteAfter,
+    PasteBefore,
+    Undo,
+    Redo,
+    Repeat,
+    Visual,
+    Escape,
+}
+
+impl Command {
+    /// Whether `.` should repeat this command.
+    pub fn is_change(self) -> bool {
+        !matches!(
+            self,
+            Command::Move(_)
+                | Command::Operate(Operator::Yank, _)
+                | Command::OperateLines(Operator::Yank)
+                | Command::OperateSelection(_)
+                | Command::Undo
+                | Command::Redo
+                | Command::Repeat
+                | Command::Visual
+                | Command::Escape
+        )
+    }
+}
+
+pub enum Parsed {
+    Pending,
+    Invalid,
+    /// The key is not part of the modal grammar and should be handled by the
+    /// regular key bindings.
+    Passthrough,
+    Done(Option<usize>, Command),
+}
+
+#[derive(Default)]
+pub struct Register {
+    pub text: String,
+    pub linewise: bool,
+}
+
+pub struct Vim {
+    pub mode: Mode,
+    pub register: Register,
+    pending: Vec<Key>,
+    change: Option<Vec<Key>>,
+    last_change: Vec<Key>,
+}
+
+impl Default for Vim {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Normal,
+            register: Register::default(),
+            pending: Vec::new(),
+            change: None,
+            last_change: Vec::new(),
+        }
+    }
+}
+
+impl Vim {
+    /// Adds `key` to the command being typed in normal or visual mode.
+    pub fn push(&mut self, key: Key) -> Parsed {
+        self.pending.push(key);
+        let parsed = parse(&self.pending, self.mode == Mode::Visual);
+        if !matches!(parsed, Parsed::Pending) {
+            let keys = std::mem::take(&mut self.pending);
+            if let Parsed::Done(_, command) = parsed {
+                if command.is_change() {
+                    self.change = Some(keys);
+                }
+            }
+        }
+        parsed
+    }
+
+    /// Records a key typed in insert mode as part of the change in progress.
+    pub fn push_insert(&mut self, key: Key) {
+        if let Some(change) = &mut self.change {
+            change.push(key);
+        }
+    }
+
+    /// Marks the end of a change, making it the one `.` repeats.
+    pub fn finish_change(&mut self) {
+        if let Some(change) = self.change.take() {
+            self.last_change = change;
+        }
+    }
+
+    pub fn last_change(&self) -> &[Key] {
+        &self.last_change
+    }
+}
+
+fn parse(keys: &[Key], visual: bool) -> Parsed {
+    let mut index = 0;
+    let count = parse_count(keys, &mut index);
+    let Some(key) = keys.get(index) else {
+        return Parsed::Pending;
+    };
+    let command = match key {
+        Key::Char('d' | 'x') if visual => Command::OperateSelection(Operator::Delete),
+        Key::Char('c' | 's') if visual => Command::OperateSelection(Operator::Change),
+        Key::Char('y') if visual => Command::OperateSelection(Operator::Yank),
+        Key::Char('v') | Key::Esc if visual => Command::Escape,
+        Key::Char(c @ ('d' | 'c' | 'y')) => {
+            let operator = match c {
+                'd' => Operator::Delete,
+                'c' => Operator::Change,
+                _ => Operator::Yank,
+            };
+            index += 1;
+            let motion_count = parse_count(keys, &mut index);
+            if keys.get(index) == Some(key) {
+                let count = multiply(count, motion_count);
+                return Parsed::Done(count, Command::OperateLines(operator));
+            }
+            return match parse_motion(&keys[index..]) {
+                Parsed::Done(_, Command::Move(motion)) => Parsed::Done(
+                    multiply(count, motion_count),
+                    Command::Operate(operator, motion),
+                ),
+                other => other,
+            };
+        }
+        Key::Char('D') => Command::Operate(Operator::Delete, Motion::LineEnd),
+        Key::Char('C') => Command::Operate(Operator::Change, Motion::LineEnd),
+        Key::Char('Y') => Command::OperateLines(Operator::Yank),
+        Key::Char('i') => Command::Insert,
+        Key::Char('a') => Command::InsertAfter,
+        Key::Char('I') => Command::InsertLineStart,
+        Key::Char('A') => Command::InsertLineEnd,
+        Key::Char('o') => Command::OpenBelow,
+        Key::Char('O') => Command::OpenAbove,
+        Key::Char('x') | Key::Delete => Command::DeleteChar,
+        Key::Char('X') => Command::DeleteCharBefore,
+        Key::Char('p') => Command::PasteAfter,
+        Key::Char('P') => Command::PasteBefore,
+        Key::Char('u') => Command::Undo,
+        Key::Ctrl('r') => Command::Redo,
+        Key::Char('.') => Command::Repeat,
+        Key::Char('v') => Command::Visual,
+        Key::Esc => Command::Escape,
+        Key::Char(_)
+        | Key::Left
+        | Key::Right
+        | Key::Up
+        | Key::Down
+        | Key::Home
+        | Key::End
+        | Key::Backspace => {
+            return match parse_motion(&keys[index..]) {
+                Parsed::Done(_, command) => Parsed::Done(count, command),
+                other => other,
+            }
+        }
+        _ if index == 0 => return Parsed::Passthrough,
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Done(count, command)
+}
+
+fn parse_count(keys: &[Key], index: &mut usize) -> Option<usize> {
+    let mut count: Option<usize> = None;
+    while let Some(Key::Char(c)) = keys.get(*index) {
+        let Some(digit) = c.to_digit(10) else {
+            break;
+        };
+        if digit == 0 && count.is_none() {
+            // A leading zero is the line start motion.
+            break;
+        }
+        count = Some(
+            count
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit as usize),
+        );
+        *index += 1;
+    }
+    count
+}
+
+fn parse_motion(keys: &[Key]) -> Parsed {
+    let Some(key) = keys.first() else {
+        return Parsed::Pending;
+    };
+    let motion = match key {
+        Key::Char('h') | Key::Left | Key::Backspace => Motion::Left,
+        Key::Char('l' | ' ') | Key::Right => Motion::Right,
+        Key::Char('k') | Key::Up => Motion::Up,
+        Key::Char('j' | '\n') | Key::Down => Motion::Down,
+        Key::Char('w') => Motion::WordForward,
+        Key::Char('b') => Motion::WordBackward,
+        Key::Char('e') => Motion::WordEnd,
+        Key::Char('0') | Key::Home => Motion::LineStart,
+        Key::Char('$') | Key::End => Motion::LineEnd,
+        Key::Char('G') => Motion::LastLine,
+        Key::Char('g') => match keys.get(1) {
+            None => return Parsed::Pending,
+            Some(Key::Char('g')) => Motion::FirstLine,
+            Some(_) => return Parsed::Invalid,
+        },
+        Key::Char(c @ ('f' | 't' | 'F' | 'T')) => match keys.get(1) {
+            None => return Parsed::Pending,
+            Some(Key::Char(target)) => match c {
+                'f' => Motion::FindForward(*target),
+                't' => Motion::TillForward(*target),
+                'F' => Motion::FindBackward(*target),
+                _ => Motion::TillBackward(*target),
+            },
+            Some(_) => return Parsed::Invalid,
+        },
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Done(None, Command::Move(motion))
+}
+
+fn multiply(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[derive(PartialEq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// The character at `at`, with the end of each row read as a newline.
+fn char_at(document: &Document, at: &Position) -> char {
+    document
+        .row(at.y)
+        .and_then(|row| row.char_at(at.x))
+        .unwrap_or('\n')
+}
+
+fn next(document: &Document, at: &Position) -> Option<Position> {
+    let width = document.row(at.y)?.len();
+    if at.x < width {
+        Some(Position {
+            x: at.x + 1,
+            y: at.y,
+        })
+    } else if at.y.saturating_add(1) < document.len() {
+        Some(Position { x: 0, y: at.y + 1 })
+    } else {
+        None
+    }
+}
+
+fn previous(document: &Document, at: &Position) -> Option<Position> {
+    if at.x > 0 {
+        Some(Position {
+            x: at.x - 1,
+            y: at.y,
+        })
+    } else if at.y > 0 {
+        let y = at.y - 1;
+        let x = document.row(y).map_or(0, crate::Row::len);
+        Some(Position { x, y })
+    } else {
+        None
+    }
+}
+
+fn word_forward(document: &Document, from: &Position) -> Position {
+    let mut at = from.clone();
+    let start = class(char_at(document, &at));
+    if start != CharClass::Blank {
+        while let Some(next) = next(document, &at) {
+            at = next;
+            if class(char_at(document, &at)) != start {
+                break;
+            }
+        }
+    }
+    while class(char_at(document, &at)) == CharClass::Blank {
+        match next(document, &at) {
+            Some(next) => at = next,
+            None => break,
+        }
+    }
+    at
+}
+
+fn word_backward(document: &Document, from: &Position) -> Position {
+    let Some(mut at) = previous(document, from) else {
+        return from.clone();
+    };
+    while class(char_at(document, &at)) == CharClass::Blank {
+        match previous(document, &at) {
+            Some(previous) => at = previous,
+            None => return at,
+        }
+    }
+    let word = class(char_at(document, &at));
+    while let Some(previous) = previous(document, &at) {
+        if class(char_at(document, &previous)) != word {
+            break;
+        }
+        at = previous;
+    }
+    at
+}
+
+fn word_end(document: &Document, from: &Position) -> Position {
+    let Some(mut at) = next(document, from) else {
+        return from.clone();
+    };
+    while class(char_at(document, &at)) == CharClass::Blank {
+        match next(document, &at) {
+            Some(next) => at = next,
+            None => return at,
+        }
+    }
+    let word = class(char_at(document, &at));
+    while let Some(next) = next(document, &at) {
+        if class(char_at(document, &next)) != word {
+            break;
+        }
+        at = next;
+    }
+    at
+}
+
+fn find_in_row(
+    document: &Document,
+    from: &Position,
+    target: char,
+    forward: bool,
+    count: usize,
+) -> Option<usize> {
+    let row = document.row(from.y)?;
+    let mut x = from.x;
+    for _ in 0..count {
+        loop {
+            if forward {
+                x = x.checked_add(1).filter(|x| *x < row.len())?;
+            } else {
+                x = x.checked_sub(1)?;
+            }
+            if row.char_at(x) == Some(target) {
+                break;
+            }
+        }
+    }
+    Some(x)
+}
+
+pub fn first_non_blank(document: &Document, y: usize) -> usize {
+    let Some(row) = document.row(y) else {
+        return 0;
+    };
+    (0..row.len())
+        .find(|x| !row.char_at(*x).is_some_and(char::is_whitespace))
+        .unwrap_or(0)
+}
+
+/// Where `motion` repeated `count` times (or with an explicit line number for
+/// `gg` and `G`) moves the cursor, or `None` if it can't move.
+pub fn target(
+    document: &Document,
+    from: &Position,
+    motion: Motion,
+    count: Option<usize>,
+) -> Option<Position> {
+    let times = count.unwrap_or(1);
+    let last_line = document.len().saturating_sub(1);
+    let mut at = from.clone();
+    match motion {
+        Motion::Left => at.x = at.x.saturating_sub(times),
+        Motion::Right => {
+            let width = document.row(at.y).map_or(0, crate::Row::len);
+            at.x = at.x.saturating_add(times).min(width);
+        }
+        Motion::Up => at.y = at.y.saturating_sub(times),
+        Motion::Down => at.y = at.y.saturating_add(times).min(last_line),
+        Motion::WordForward => {
+            for _ in 0..times {
+                at = word_forward(document, &at);
+            }
+        }
+        Motion::WordBackward => {
+            for _ in 0..times {
+                at = word_backward(document, &at);
+            }
+        }
+        Motion::WordEnd => {
+            for _ in 0..times {
+                at = word_end(document, &at);
+            }
+        }
+        Motion::LineStart => at.x = 0,
+        Motion::LineEnd => {
+            at.y = at.y.saturating_add(times - 1).min(last_line);
+            at.x = document
+                .row(at.y)
+                .map_or(0, crate::Row::len)
+                .saturating_sub(1);
+        }
+        Motion::FirstLine | Motion::LastLine => {
+            at.y = match (motion, count) {
+                (_, Some(line)) => line.saturating_sub(1).min(last_line),
+                (Motion::FirstLine, None) => 0,
+                _ => last_line,
+            };
+            at.x = first_non_blank(document, at.y);
+        }
+        Motion::FindForward(c) => at.x = find_in_row(document, &at, c, true, times)?,
+        Motion::TillForward(c) => {
+            at.x = find_in_row(document, &at, c, true, times)?.saturating_sub(1);
+        }
+        Motion::FindBackward(c) => at.x = find_in_row(document, &at, c, false, times)?,
+        Motion::TillBackward(c) => {
+            at.x = find_in_row(document, &at, c, false, times)?.saturating_add(1);
+        }
+    }
+    Some(at)
+}
+
+/// Where `operator` with `motion` acts up to from `from`, and the motion it
+/// then acts with. Like Vim, `cw` on a word changes to its end rather than
+/// swallowing the blanks that follow it, and `dw` on the last word of a row
+/// stops at the end of the row.
+pub fn operator_target(
+    document: &Document,
+    from: &Position,
+    operator: Operator,
+    motion: Motion,
+    count: Option<usize>,
+) -> Option<(Position, Motion)> {
+    let word = class(char_at(document, from));
+    if operator == Operator::Change && motion == Motion::WordForward && word != CharClass::Blank {
+        // On the last character of a word, the first word end is right there.
+        let next = Position {
+            x: from.x.saturating_add(1),
+            y: from.y,
+        };
+        let skip = usize::from(class(char_at(document, &next)) != word);
+        let mut at = from.clone();
+        for _ in skip..count.unwrap_or(1) {
+            at = word_end(document, &at);
+        }
+        return Some((at, Motion::WordEnd));
+    }
+    let mut to = target(document, from, motion, count)?;
+    if motion == Motion::WordForward && to.y > from.y {
+        to = Position {
+            x: document.row(from.y).map_or(0, crate::Row::len),
+            y: from.y,
+        };
+    }
+    Some((to, motion))
+}
+
+/// The span an operator with `motion` acts on, from `from` to `to`, as a
+/// start and an exclusive end.
+pub fn operator_range(
+    document: &Document,
+    from: &Position,
+    to: &Position,
+    motion: Motion,
+) -> (Position, Position) {
+    let (start, end) = ordered(from, to);
+    if motion.is_linewise() {
+        return line_range(document, start.y, end.y);
+    }
+    if motion.is_inclusive() {
+        let end = Position {
+            x: end.x.saturating_add(1),
+            y: end.y,
+        };
+        return (start, end);
+    }
+    (start, end)
+}
+
+/// The span covering rows `first` to `last` including their line breaks, so
+/// removing it removes the lines entirely.
+pub fn line_range(document: &Document, first: usize, last: usize) -> (Position, Position) {
+    if last.saturating_add(1) < document.len() {
+        return (
+            Position { x: 0, y: first },
+            Position {
+                x: 0,
+                y: last.saturating_add(1),
+            },
+        );
+    }
+    let end = document.end_position();
+    if first == 0 {
+        return (Position::default(), end);
+    }
+    let y = first - 1;
+    let x = document.row(y).map_or(0, crate::Row::len);
+    (Position { x, y }, end)
+}
+
+pub fn ordered(a: &Position, b: &Position) -> (Position, Position) {
+    if (a.y, a.x) <= (b.y, b.x) {
+        (a.clone(), b.clone())
+    } else {
+        (b.clone(), a.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(text: &str) -> Vec<Key> {
+        text.chars().map(Key::Char).collect()
+    }
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::default();
+        document.replace(&Position::default(), &Position::default(), text);
+        document
+    }
+
+    fn command(text: &str) -> Option<(Option<usize>, Command)> {
+        match parse(&keys(text), false) {
+            Parsed::Done(count, command) => Some((count, command)),
+            _ => None,
+        }
+    }
+
+    fn moved(
+        text: &str,
+        from: (usize, usize),
+        motion: Motion,
+        count: Option<usize>,
+    ) -> (usize, usize) {
+        let from = Position {
+            x: from.0,
+            y: from.1,
+        };
+        let at = target(&document(text), &from, motion, count).unwrap();
+        (at.x, at.y)
+    }
+
+    /// The text an operator command typed as `command` removes or copies
+    /// with the cursor at `from`.
+    fn operated(text: &str, from: (usize, usize), command_keys: &str) -> String {
+        let document = document(text);
+        let from = Position {
+            x: from.0,
+            y: from.1,
+        };
+        let (start, end) = match command(command_keys) {
+            Some((count, Command::OperateLines(_))) => {
+                let last = from.y + count.unwrap_or(1) - 1;
+                line_range(&document, from.y, last)
+            }
+            Some((count, Command::Operate(operator, motion))) => {
+                let (to, motion) =
+                    operator_target(&document, &from, operator, motion, count).unwrap();
+                if motion.is_linewise() {
+                    line_range(&document, from.y.min(to.y), from.y.max(to.y))
+                } else {
+                    operator_range(&document, &from, &to, motion)
+                }
+            }
+            _ => panic!("`{}` is not an operator command", command_keys),
+        };
+        document.text(&start, &end)
+    }
+
+    #[test]
+    fn parses_counts_operators_and_motions() {
+        assert!(command("3j") == Some((Some(3), Command::Move(Motion::Down))));
+        assert!(command("0") == Some((None, Command::Move(Motion::LineStart))));
+        assert!(command("10G") == Some((Some(10), Command::Move(Motion::LastLine))));
+        assert!(command("gg") == Some((None, Command::Move(Motion::FirstLine))));
+        assert!(command("fa") == Some((None, Command::Move(Motion::FindForward('a')))));
+        assert!(
+            command("dw")
+                == Some((
+                    None,
+                    Command::Operate(Operator::Delete, Motion::WordForward)
+                ))
+        );
+        assert!(
+            command("2d3w")
+                == Some((
+                    Some(6),
+                    Command::Operate(Operator::Delete, Motion::WordForward)
+                ))
+        );
+        assert!(command("dd") == Some((None, Command::OperateLines(Operator::Delete))));
+        assert!(command("3yy") == Some((Some(3), Command::OperateLines(Operator::Yank))));
+        assert!(command(".") == Some((None, Command::Repeat)));
+        assert!(matches!(parse(&keys("d"), false), Parsed::Pending));
+        assert!(matches!(parse(&keys("3"), false), Parsed::Pending));
+        assert!(matches!(parse(&keys("g"), false), Parsed::Pending));
+        assert!(matches!(parse(&keys("gx"), false), Parsed::Invalid));
+        assert!(matches!(parse(&keys("dQ"), false), Parsed::Invalid));
+        assert!(matches!(
+            parse(&[Key::Ctrl('s')], false),
+            Parsed::Passthrough
+        ));
+        assert!(matches!(
+            parse(&keys("d"), true),
+            Parsed::Done(None, Command::OperateSelection(Operator::Delete))
+        ));
+    }
+
+    #[test]
+    fn moves_by_motions() {
+        let text = "one two\n  three\nfour\nfive";
+        assert_eq!(moved(text, (0, 0), Motion::Down, Some(3)), (0, 3));
+        assert_eq!(moved(text, (0, 0), Motion::Down, Some(9)), (0, 3));
+        assert_eq!(moved(text, (4, 0), Motion::WordForward, None), (2, 1));
+        assert_eq!(moved(text, (0, 0), Motion::WordForward, Some(2)), (2, 1));
+        assert_eq!(moved(text, (2, 1), Motion::WordBackward, None), (4, 0));
+        assert_eq!(moved(text, (0, 0), Motion::WordEnd, None), (2, 0));
+        assert_eq!(moved(text, (0, 0), Motion::LineEnd, None), (6, 0));
+        assert_eq!(moved(text, (0, 0), Motion::FindForward('o'), None), (6, 0));
+        assert_eq!(moved(text, (0, 0), Motion::TillForward('t'), None), (3, 0));
+        assert_eq!(moved(text, (0, 0), Motion::LastLine, Some(2)), (2, 1));
+        assert_eq!(moved(text, (3, 3), Motion::FirstLine, None), (0, 0));
+        let document = document(text);
+        let from = Position::default();
+        assert!(target(&document, &from, Motion::FindForward('z'), None).is_none());
+    }
+
+    #[test]
+    fn operates_on_words() {
+        assert_eq!(operated("foo bar", (0, 0), "dw"), "foo ");
+        assert_eq!(operated("foo bar baz", (0, 0), "d2w"), "foo bar ");
+        assert_eq!(operated("foo bar", (0, 0), "de"), "foo");
+        assert_eq!(operated("foo bar\nbaz", (4, 0), "dw"), "bar");
+        assert_eq!(operated("foo bar", (0, 0), "cw"), "foo");
+        assert_eq!(operated("foo bar", (2, 0), "cw"), "o");
+        assert_eq!(operated("foo bar", (2, 0), "2cw"), "o bar");
+        assert_eq!(operated("foo  bar", (3, 0), "cw"), "  ");
+        assert_eq!(operated("foo bar", (1, 0), "d$"), "oo bar");
+        assert_eq!(operated("foo bar", (0, 0), "dtr"), "foo ba");
+    }
+
+    #[test]
+    fn operates_on_lines() {
+        assert_eq!(operated("a\nb\nc", (0, 1), "dd"), "b\n");
+        assert_eq!(operated("a\nb\nc", (0, 2), "dd"), "\nc");
+        assert_eq!(operated("a\nb\nc", (0, 1), "2dd"), "\nb\nc");
+        assert_eq!(operated("a", (0, 0), "dd"), "a");
+        assert_eq!(operated("a\nb\nc", (0, 0), "dj"), "a\nb\n");
+        assert_eq!(operated("a\nb\nc", (0, 2), "dk"), "\nb\nc");
+    }
+
+    #[test]
+    fn remembers_the_last_change_for_repeat() {
+        let mut vim = Vim::default();
+        for key in keys("2dw") {
+            vim.push(key);
+        }
+        vim.finish_change();
+        assert_eq!(vim.last_change(), keys("2dw"));
+        for key in keys("jyy") {
+            vim.push(key);
+        }
+        vim.finish_change();
+        assert_eq!(vim.last_change(), keys("2dw"));
+        for key in keys("cw") {
+            vim.push(key);
+        }
+        vim.push_insert(Key::Char('x'));
+        vim.push_insert(Key::Esc);
+        vim.finish_change();
+        let mut change = keys("cwx");
+        change.push(Key::Esc);
+        assert_eq!(vim.last_change(), change);
+        assert!(matches!(
+            vim.push(Key::Char('.')),
+            Parsed::Done(None, Command::Repeat)
+        ));
+        vim.finish_change();
+        assert_eq!(vim.last_change(), change);
+    }
+}